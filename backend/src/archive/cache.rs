@@ -0,0 +1,154 @@
+use crate::archive::{Container, open_nested, open_nested_spooled, outer_path};
+use crate::error::Result;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: String,
+    container: Option<String>,
+    modified: SystemTime,
+    size: u64,
+}
+
+struct Slot {
+    key: Key,
+    archive: Box<dyn Container>,
+    /// Bytes of inner archives held for this one, see [`open_nested_spooled`].
+    spooled: u64,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    idle: Vec<Slot>,
+    tick: u64,
+}
+
+/// Keeps recently used archives open with their index parsed, so reading a
+/// book page by page doesn't re-read the central directory on every request.
+/// Entries are keyed by the outermost file's modified time and size, a
+/// changed file is parsed again.
+#[derive(Clone)]
+pub struct Cache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+    max_spooled: u64,
+    max_spool: u64,
+}
+
+impl Cache {
+    /// `max_spooled` caps what the kept archives hold between them,
+    /// `max_spool` what a single nested archive may take to be opened at all.
+    pub fn new(capacity: usize, max_spooled: u64, max_spool: u64) -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity,
+            max_spooled,
+            max_spool,
+        }
+    }
+
+    pub fn max_spool(&self) -> u64 {
+        self.max_spool
+    }
+
+    /// Runs `f` with the archive at `path`, which may be a nested chain. The
+    /// archive is taken out of the cache while `f` runs and put back after, a
+    /// request coming in meanwhile opens another one instead of waiting.
+    pub fn with<T>(
+        &self,
+        path: &str,
+        container: Option<&str>,
+        f: impl FnOnce(&mut dyn Container) -> Result<T>,
+    ) -> Result<T> {
+        if self.capacity == 0 {
+            return f(open_nested(path, container, self.max_spool)?.as_mut());
+        }
+
+        let meta = fs::metadata(outer_path(path)?)?;
+
+        let key = Key {
+            path: path.to_string(),
+            container: container.map(|it| it.to_string()),
+            modified: meta.modified()?,
+            size: meta.len(),
+        };
+
+        let mut slot = match self.take(&key)? {
+            Some(slot) => slot,
+            None => {
+                let (archive, spooled) = open_nested_spooled(path, container, self.max_spool)?;
+
+                Slot {
+                    key,
+                    archive,
+                    spooled,
+                    used: 0,
+                }
+            }
+        };
+
+        let res = f(slot.archive.as_mut());
+        self.put(slot)?;
+
+        res
+    }
+
+    fn take(&self, key: &Key) -> Result<Option<Slot>> {
+        let mut inner = self.inner.lock()?;
+
+        // older versions of a changed file won't be asked for again
+        inner
+            .idle
+            .retain(|it| it.key.path != key.path || it.key == *key);
+
+        Ok(inner
+            .idle
+            .iter()
+            .position(|it| it.key == *key)
+            .map(|index| inner.idle.swap_remove(index)))
+    }
+
+    fn put(&self, mut slot: Slot) -> Result<()> {
+        if slot.spooled > self.max_spooled {
+            return Ok(());
+        }
+
+        let mut inner = self.inner.lock()?;
+        inner.tick += 1;
+        slot.used = inner.tick;
+
+        inner.idle.push(slot);
+
+        while inner.idle.len() > self.capacity
+            || inner.idle.iter().map(|it| it.spooled).sum::<u64>() > self.max_spooled
+        {
+            let oldest = inner
+                .idle
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, it)| it.used)
+                .map(|(index, _)| index);
+
+            match oldest {
+                Some(index) => inner.idle.swap_remove(index),
+                None => break,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .field("max_spooled", &self.max_spooled)
+            .field("max_spool", &self.max_spool)
+            .finish_non_exhaustive()
+    }
+}
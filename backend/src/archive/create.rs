@@ -0,0 +1,381 @@
+use crate::archive::{Kind, archive_extension};
+use crate::error::{Error, Result};
+use crate::fs::{TransferFailure, TransferReport, temp_sibling, tree_size};
+use crate::jobs::JobHandle;
+use axum::http::StatusCode;
+use chrono::{DateTime as ChronoDateTime, Datelike, Local, Timelike};
+use flate2::Compression as GzCompression;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use tar::{Builder as TarBuilder, Header};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Zip,
+    TarGz,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOptions {
+    /// Guessed from the destination name when not given, zip otherwise.
+    pub format: Option<Format>,
+    /// 0 to 9, the format's default when not given.
+    pub level: Option<u32>,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+trait Writer {
+    fn add_dir(&mut self, name: &str, meta: &Metadata) -> Result<()>;
+    fn add_file(&mut self, name: &str, meta: &Metadata, reader: &mut dyn Read) -> Result<()>;
+    fn add_symlink(&mut self, name: &str, meta: &Metadata, target: &Path) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<File>;
+}
+
+struct Creation<'a> {
+    writer: Box<dyn Writer>,
+    job: &'a JobHandle,
+    // the archive being written may sit inside one of the sources
+    skip: PathBuf,
+    total: u64,
+    report: TransferReport,
+}
+
+/// Packs the given files and directories into a new archive at `dst`. Each
+/// path is stored under its own name. Entries that can't be read are skipped
+/// and recorded, an error once one is being written ends the job since the
+/// archive would be left broken.
+pub fn create(
+    paths: &[PathBuf],
+    dst: &Path,
+    options: &CreateOptions,
+    job: &JobHandle,
+) -> Result<TransferReport> {
+    if !options.overwrite && dst.symlink_metadata().is_ok() {
+        return Err(Error::new(format!("already exists: {}", dst.display()))
+            .with_status(StatusCode::CONFLICT));
+    }
+
+    if options.level.is_some_and(|it| it > 9) {
+        return Err(Error::new("compression level must be between 0 and 9"));
+    }
+
+    let format = match options.format {
+        Some(format) => format,
+        None => {
+            let name = dst.file_name().unwrap_or_default().to_string_lossy();
+
+            match Kind::from_file_name(&name) {
+                Some(Kind::TarGz) => Format::TarGz,
+                Some(Kind::Zip) | None => Format::Zip,
+                Some(_) => {
+                    let ext = archive_extension(&name).unwrap_or_default();
+                    return Err(Error::new(format!(
+                        "can't create .{ext} archives, use zip or tar.gz"
+                    )));
+                }
+            }
+        }
+    };
+
+    let mut total = 0;
+
+    for path in paths {
+        total += tree_size(path, job)?.bytes;
+    }
+
+    let temp_path = temp_sibling(dst)?;
+    let file = File::create(&temp_path)?;
+
+    let writer: Box<dyn Writer> = match format {
+        Format::Zip => Box::new(Zip::new(file, options.level)),
+        Format::TarGz => Box::new(Tar::new(file, options.level)),
+    };
+
+    let creation = Creation {
+        writer,
+        job,
+        skip: std::path::absolute(&temp_path)?,
+        total,
+        report: TransferReport::default(),
+    };
+
+    let res = creation.run(paths).and_then(|(file, report)| {
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, dst)?;
+
+        Ok(report)
+    });
+
+    if res.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    res
+}
+
+impl Creation<'_> {
+    fn run(mut self, paths: &[PathBuf]) -> Result<(File, TransferReport)> {
+        for path in paths {
+            let name = path
+                .file_name()
+                .ok_or_else(|| Error::new(format!("invalid path: {}", path.display())))?;
+
+            self.add_entry(path, &name.to_string_lossy())?;
+        }
+
+        Ok((self.writer.finish()?, self.report))
+    }
+
+    fn add_entry(&mut self, path: &Path, name: &str) -> Result<()> {
+        self.job.check()?;
+
+        if std::path::absolute(path)? == self.skip {
+            return Ok(());
+        }
+
+        // nothing has been written for the entry until this succeeds
+        let entry = match open_entry(path) {
+            Ok(entry) => entry,
+            Err(err) => return self.skip_entry(path, err),
+        };
+
+        match entry {
+            Entry::Dir(meta) => {
+                self.writer.add_dir(name, &meta)?;
+
+                let entries = match fs::read_dir(path) {
+                    Ok(entries) => entries,
+                    Err(err) => return self.skip_entry(path, err.into()),
+                };
+
+                for entry in entries {
+                    match entry {
+                        Ok(entry) => {
+                            let child = format!("{name}/{}", entry.file_name().to_string_lossy());
+                            self.add_entry(&entry.path(), &child)?;
+                        }
+                        Err(err) => self.skip_entry(path, err.into())?,
+                    }
+                }
+            }
+
+            Entry::Symlink(meta, target) => {
+                self.writer.add_symlink(name, &meta, &target)?;
+                self.report.files += 1;
+            }
+
+            Entry::File(meta, file) => {
+                let Self {
+                    writer,
+                    job,
+                    total,
+                    report,
+                    ..
+                } = self;
+
+                let mut reader = ProgressReader {
+                    reader: file,
+                    job,
+                    bytes: &mut report.bytes,
+                    total: *total,
+                    message: Some(name.to_string()),
+                };
+
+                writer.add_file(name, &meta, &mut reader)?;
+                self.report.files += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn skip_entry(&mut self, path: &Path, err: Error) -> Result<()> {
+        if self.job.is_cancelled() {
+            return Err(err);
+        }
+
+        self.report.failures.push(TransferFailure {
+            path: path.to_path_buf(),
+            message: err.message,
+        });
+
+        Ok(())
+    }
+}
+
+enum Entry {
+    Dir(Metadata),
+    Symlink(Metadata, PathBuf),
+    File(Metadata, File),
+}
+
+fn open_entry(path: &Path) -> Result<Entry> {
+    let meta = path.symlink_metadata()?;
+
+    Ok(if meta.is_dir() {
+        Entry::Dir(meta)
+    } else if meta.is_symlink() {
+        let target = fs::read_link(path)?;
+        Entry::Symlink(meta, target)
+    } else {
+        Entry::File(meta, File::open(path)?)
+    })
+}
+
+struct ProgressReader<'a> {
+    reader: File,
+    job: &'a JobHandle,
+    bytes: &'a mut u64,
+    total: u64,
+    message: Option<String>,
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.job
+            .check()
+            .map_err(|err| io::Error::other(err.message))?;
+
+        let read = self.reader.read(buf)?;
+        *self.bytes += read as u64;
+        self.job
+            .progress(*self.bytes, Some(self.total), self.message.clone());
+
+        Ok(read)
+    }
+}
+
+struct Zip {
+    writer: ZipWriter<BufWriter<File>>,
+    level: Option<u32>,
+}
+
+impl Zip {
+    fn new(file: File, level: Option<u32>) -> Self {
+        Self {
+            writer: ZipWriter::new(BufWriter::new(file)),
+            level,
+        }
+    }
+
+    fn options(&self, meta: &Metadata) -> SimpleFileOptions {
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(self.level.map(i64::from))
+            .large_file(meta.len() >= u32::MAX as u64);
+
+        if let Some(time) = meta.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(time);
+        }
+
+        if let Some(mode) = unix_mode(meta) {
+            options = options.unix_permissions(mode);
+        }
+
+        options
+    }
+}
+
+impl Writer for Zip {
+    fn add_dir(&mut self, name: &str, meta: &Metadata) -> Result<()> {
+        let options = self.options(meta);
+        Ok(self.writer.add_directory(name, options)?)
+    }
+
+    fn add_file(&mut self, name: &str, meta: &Metadata, reader: &mut dyn Read) -> Result<()> {
+        let options = self.options(meta);
+        self.writer.start_file(name, options)?;
+        io::copy(reader, &mut self.writer)?;
+
+        Ok(())
+    }
+
+    fn add_symlink(&mut self, name: &str, meta: &Metadata, target: &Path) -> Result<()> {
+        let options = self.options(meta);
+        let target = target.to_string_lossy();
+
+        Ok(self.writer.add_symlink(name, target, options)?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<File> {
+        let writer = self.writer.finish()?;
+        Ok(writer.into_inner().map_err(|err| err.into_error())?)
+    }
+}
+
+struct Tar {
+    builder: TarBuilder<GzEncoder<BufWriter<File>>>,
+}
+
+impl Tar {
+    fn new(file: File, level: Option<u32>) -> Self {
+        let level = level.map(GzCompression::new).unwrap_or_default();
+        let mut builder = TarBuilder::new(GzEncoder::new(BufWriter::new(file), level));
+        builder.follow_symlinks(false);
+
+        Self { builder }
+    }
+}
+
+impl Writer for Tar {
+    fn add_dir(&mut self, name: &str, meta: &Metadata) -> Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_metadata(meta);
+
+        Ok(self.builder.append_data(&mut header, name, io::empty())?)
+    }
+
+    fn add_file(&mut self, name: &str, meta: &Metadata, reader: &mut dyn Read) -> Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_metadata(meta);
+
+        Ok(self.builder.append_data(&mut header, name, reader)?)
+    }
+
+    fn add_symlink(&mut self, name: &str, meta: &Metadata, target: &Path) -> Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_metadata(meta);
+
+        Ok(self.builder.append_link(&mut header, name, target)?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<File> {
+        let writer = self.builder.into_inner()?.finish()?;
+        Ok(writer.into_inner().map_err(|err| err.into_error())?)
+    }
+}
+
+// zip stores local time without an offset and can't go before 1980
+fn zip_time(time: std::time::SystemTime) -> Option<DateTime> {
+    let time: ChronoDateTime<Local> = time.into();
+
+    DateTime::from_date_and_time(
+        time.year().try_into().ok()?,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .ok()
+}
+
+#[cfg(unix)]
+fn unix_mode(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_: &Metadata) -> Option<u32> {
+    None
+}
@@ -0,0 +1,239 @@
+use crate::archive::{Entry, open_nested};
+use crate::error::{Error, Result};
+use crate::fs::{TransferFailure, TransferReport, chmod, set_times, unique_name};
+use crate::jobs::JobHandle;
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Conflict {
+    #[default]
+    Error,
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractOptions {
+    /// Entry names to extract, a directory takes everything under it along.
+    pub entries: Option<Vec<String>>,
+    #[serde(default)]
+    pub on_conflict: Conflict,
+}
+
+struct Extraction<'a> {
+    root: PathBuf,
+    options: &'a ExtractOptions,
+    job: &'a JobHandle,
+    done: u64,
+    total: u64,
+    report: TransferReport,
+}
+
+/// Extracts the archive into `dst`, recording per-entry failures instead of aborting.
+/// Entries that would land outside of `dst` are refused.
+pub fn extract(
+    path: &str,
+    container: Option<&str>,
+    max_spool: u64,
+    dst: &Path,
+    options: &ExtractOptions,
+    job: &JobHandle,
+) -> Result<TransferReport> {
+    let mut archive = open_nested(path, container, max_spool)?;
+
+    fs::create_dir_all(dst)?;
+
+    let total = archive
+        .entries()?
+        .iter()
+        .filter(|it| !it.is_dir && is_selected(&it.name, options))
+        .map(|it| it.size)
+        .sum();
+
+    let mut extraction = Extraction {
+        root: dst.canonicalize()?,
+        options,
+        job,
+        done: 0,
+        total,
+        report: TransferReport::default(),
+    };
+
+    archive.walk(&mut |entry, reader| {
+        job.check()?;
+
+        if !is_selected(&entry.name, options) {
+            return Ok(());
+        }
+
+        match extraction.extract_entry(entry, reader) {
+            Err(err) if job.is_cancelled() => Err(err),
+            Err(err) => {
+                extraction.report.failures.push(TransferFailure {
+                    path: PathBuf::from(&entry.name),
+                    message: err.message,
+                });
+
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    })?;
+
+    Ok(extraction.report)
+}
+
+fn is_selected(name: &str, options: &ExtractOptions) -> bool {
+    let Some(entries) = &options.entries else {
+        return true;
+    };
+
+    entries.iter().any(|it| {
+        let it = it.trim_end_matches('/');
+        name.trim_end_matches('/') == it
+            || name
+                .strip_prefix(it)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Turns an entry name into a relative path, rejecting absolute paths and `..`.
+fn relative_path(name: &str) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+
+    // windows archivers may write backslashes
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(it) => res.push(it),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    (!res.as_os_str().is_empty()).then_some(res)
+}
+
+impl Extraction<'_> {
+    fn extract_entry(&mut self, entry: &Entry, reader: &mut dyn Read) -> Result<()> {
+        let relative = relative_path(&entry.name)
+            .ok_or_else(|| Error::new(format!("unsafe entry path: {}", entry.name)))?;
+
+        self.check_parents(&relative)?;
+        let path = self.root.join(&relative);
+
+        if entry.is_dir {
+            return Ok(fs::create_dir_all(path)?);
+        }
+
+        if entry.encrypted {
+            return Err(Error::new("encrypted entries are not supported"));
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let Some(path) = self.resolve_conflict(path)? else {
+            self.done += entry.size;
+            self.job.progress(self.done, Some(self.total), None);
+
+            return Ok(());
+        };
+
+        let mut writer = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        let message = Some(entry.name.clone());
+        let mut buf = vec![0u8; 64 * 1024];
+
+        loop {
+            self.job.check()?;
+
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            writer.write_all(&buf[..read])?;
+            self.done += read as u64;
+            self.report.bytes += read as u64;
+            self.job
+                .progress(self.done, Some(self.total), message.clone());
+        }
+
+        drop(writer);
+
+        if entry.modified_time.is_some() {
+            set_times(&path, entry.modified_time, None)?;
+        }
+
+        // special bits are dropped, an archive shouldn't be able to plant setuid binaries
+        if let Some(mode) = entry.mode {
+            chmod(&path, mode & 0o777)?;
+        }
+
+        self.report.files += 1;
+        Ok(())
+    }
+
+    // a symlink already in the destination could redirect the entry elsewhere
+    fn check_parents(&self, relative: &Path) -> Result<()> {
+        let mut path = self.root.clone();
+
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            path.push(component);
+
+            if path.symlink_metadata().is_ok_and(|it| it.is_symlink()) {
+                return Err(Error::new(format!(
+                    "entry path goes through a symlink: {}",
+                    path.display()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns where the entry should be written, or `None` when it's skipped.
+    fn resolve_conflict(&self, path: PathBuf) -> Result<Option<PathBuf>> {
+        let meta = match path.symlink_metadata() {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Some(path)),
+            Err(err) => return Err(err.into()),
+        };
+
+        match self.options.on_conflict {
+            Conflict::Error => Err(Error::new(format!("already exists: {}", path.display()))),
+            Conflict::Skip => Ok(None),
+            Conflict::Overwrite if meta.is_dir() => {
+                Err(Error::new(format!("is a directory: {}", path.display())))
+            }
+            Conflict::Overwrite => {
+                // removed rather than truncated so a symlink isn't followed
+                fs::remove_file(&path)?;
+                Ok(Some(path))
+            }
+            Conflict::Rename => {
+                let name = path.file_name().unwrap_or_default();
+
+                for n in 2.. {
+                    let candidate = path.with_file_name(unique_name(name, n));
+
+                    if candidate.symlink_metadata().is_err() {
+                        return Ok(Some(candidate));
+                    }
+                }
+
+                unreachable!()
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+pub mod cache;
+pub mod create;
+pub mod extract;
+pub mod sevenz;
+pub mod tar;
+pub mod zip;
+
+use crate::archive::cache::Cache;
+use crate::error::{Error, Result};
+use crate::fs::{CONTAINER_SEP, temp_sibling};
+use crate::mime::{self, Category};
+use crate::utils::natural_cmp;
+use axum::http::StatusCode;
+use globset::GlobBuilder;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::{env, mem};
+
+/// Inner archives up to this size are held in memory, bigger ones are spooled to a temp file.
+pub const MEMORY_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Where the archive bytes come from, a file on disk or an inner archive read out of its parent.
+pub trait Source: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> Source for T {}
+
+/// A readable archive. Entries are handed out through a callback since some
+/// formats can only be decoded while walking the archive front to back.
+pub trait Container: Send {
+    fn entries(&mut self) -> Result<Vec<Entry>>;
+
+    fn read_entry(
+        &mut self,
+        name: &str,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()>;
+
+    /// Like [`Container::read_entry`] but starting `offset` bytes into the entry.
+    /// Formats that can't seek decode and skip up to it.
+    fn read_entry_at(
+        &mut self,
+        name: &str,
+        offset: u64,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        self.read_entry(name, &mut |reader| {
+            io::copy(&mut reader.take(offset), &mut io::sink())?;
+            f(reader)
+        })
+    }
+
+    fn entry(&mut self, name: &str) -> Result<Entry> {
+        self.entries()?
+            .into_iter()
+            .find(|it| it.name == name)
+            .ok_or_else(|| entry_not_found(name))
+    }
+
+    /// Visits the directories and regular files in archive order. Links and
+    /// other special entries are left out.
+    fn walk(&mut self, f: &mut dyn FnMut(&Entry, &mut dyn Read) -> Result<()>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: Option<u64>,
+    pub is_dir: bool,
+    pub modified_time: Option<u64>,
+    pub mode: Option<u32>,
+    pub compression: Option<String>,
+    pub crc: Option<u32>,
+    pub encrypted: bool,
+    pub mime: Option<String>,
+    pub category: Option<Category>,
+}
+
+impl Entry {
+    /// Fills in the type guessed from the name, directories have none.
+    pub fn new(name: String, size: u64, is_dir: bool) -> Self {
+        let file_type = (!is_dir).then(|| mime::detect_name(&name));
+
+        Self {
+            name,
+            size,
+            compressed_size: None,
+            is_dir,
+            modified_time: None,
+            mode: None,
+            compression: None,
+            crc: None,
+            encrypted: false,
+            mime: file_type.as_ref().map(|it| it.mime.clone()),
+            category: file_type.map(|it| it.category),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    Natural,
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ListOptions {
+    pub sort: Option<Sort>,
+    pub glob: Option<String>,
+    pub category: Option<Category>,
+    #[serde(default)]
+    pub skip_dirs: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    SevenZip,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "zip" | "cbz" | "epub" => Some(Self::Zip),
+            "tar" | "cbt" => Some(Self::Tar),
+            "tar.gz" | "tgz" => Some(Self::TarGz),
+            "tar.xz" | "txz" => Some(Self::TarXz),
+            "tar.zst" | "tzst" => Some(Self::TarZst),
+            "7z" | "cb7" => Some(Self::SevenZip),
+            _ => None,
+        }
+    }
+
+    /// Goes by the end of a file name, so `comics.2024.tar.gz` is a tar.gz
+    /// while a plain `log.gz` is no archive at all.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        archive_extension(name).and_then(|it| Self::from_name(&it))
+    }
+
+    /// Sniffs the format from the magic bytes at the start of the source.
+    pub fn detect(source: &mut dyn Source) -> Result<Self> {
+        let mut head = Vec::with_capacity(512);
+        source.take(512).read_to_end(&mut head)?;
+        source.seek(SeekFrom::Start(0))?;
+
+        match head.as_slice() {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] | [b'P', b'K', 7, 8, ..] => {
+                Ok(Self::Zip)
+            }
+            [0x1f, 0x8b, ..] => Ok(Self::TarGz),
+            [0xfd, b'7', b'z', b'X', b'Z', 0, ..] => Ok(Self::TarXz),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(Self::TarZst),
+            [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, ..] => Ok(Self::SevenZip),
+            [b'R', b'a', b'r', b'!', 0x1a, 0x07, ..] => {
+                Err(Error::new("unsupported container: rar"))
+            }
+            _ if head.get(257..262) == Some(b"ustar") => Ok(Self::Tar),
+            _ => Err(Error::new("unsupported container")),
+        }
+    }
+
+    /// A known container name wins, anything else (like `cbr`, which is
+    /// often a mislabeled zip or 7z) falls back to sniffing.
+    pub fn resolve(source: &mut dyn Source, container: Option<&str>) -> Result<Self> {
+        match container.and_then(Self::from_name) {
+            Some(kind) => Ok(kind),
+            None => Self::detect(source),
+        }
+    }
+}
+
+/// The extension of a file name, with `tar.` kept in front of compressed tarballs.
+pub fn archive_extension(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+
+    ["tar.gz", "tar.xz", "tar.zst"]
+        .iter()
+        .find(|it| name.ends_with(&format!(".{it}")))
+        .map(|it| it.to_string())
+        .or_else(|| {
+            Path::new(&name)
+                .extension()
+                .map(|it| it.to_string_lossy().into_owned())
+        })
+}
+
+pub fn open(path: &Path, container: Option<&str>) -> Result<Box<dyn Container>> {
+    open_source(Box::new(File::open(path)?), container)
+}
+
+/// Opens a chain like `bundle.zip>vol1.cbz`, each inner archive is read out of
+/// its parent. `container` only applies to the outermost one, inner ones go by
+/// their extension or get sniffed. Inner archives over `max_spool` bytes are refused.
+pub fn open_nested(
+    path: &str,
+    container: Option<&str>,
+    max_spool: u64,
+) -> Result<Box<dyn Container>> {
+    Ok(open_nested_spooled(path, container, max_spool)?.0)
+}
+
+/// Like [`open_nested`], also returning how many bytes of inner archives had
+/// to be spooled to memory or a temp file on the way.
+pub fn open_nested_spooled(
+    path: &str,
+    container: Option<&str>,
+    max_spool: u64,
+) -> Result<(Box<dyn Container>, u64)> {
+    let outer = outer_path(path)?;
+    let mut archive = open(Path::new(outer), container)?;
+    let mut spooled = 0;
+
+    for name in path[outer.len()..].split(CONTAINER_SEP).skip(1) {
+        let (source, len) = spool(archive.as_mut(), name, max_spool)?;
+        archive = open_source(source, archive_extension(name).as_deref())?;
+        spooled += len;
+    }
+
+    Ok((archive, spooled))
+}
+
+/// The file on disk a chain starts at. A file can have the separator in its
+/// name, so the whole path is tried first.
+pub fn outer_path(path: &str) -> Result<&str> {
+    if Path::new(path).try_exists()? {
+        return Ok(path);
+    }
+
+    Ok(path.split(CONTAINER_SEP).next().unwrap_or_default())
+}
+
+fn open_source(mut source: Box<dyn Source>, container: Option<&str>) -> Result<Box<dyn Container>> {
+    let res: Box<dyn Container> = match Kind::resolve(&mut source, container)? {
+        Kind::Zip => Box::new(zip::Zip::new(source)?),
+        Kind::Tar => Box::new(tar::Tar::new(source, tar::Compression::None)),
+        Kind::TarGz => Box::new(tar::Tar::new(source, tar::Compression::Gzip)),
+        Kind::TarXz => Box::new(tar::Tar::new(source, tar::Compression::Xz)),
+        Kind::TarZst => Box::new(tar::Tar::new(source, tar::Compression::Zstd)),
+        Kind::SevenZip => Box::new(sevenz::SevenZip::new(source)?),
+    };
+
+    Ok(res)
+}
+
+/// Reads an entry into memory, switching to a temp file once it grows past
+/// [`MEMORY_LIMIT`]. Entries over `max_len` are refused. Returns the source
+/// along with its length.
+fn spool(archive: &mut dyn Container, name: &str, max_len: u64) -> Result<(Box<dyn Source>, u64)> {
+    let mut res: Option<(Box<dyn Source>, u64)> = None;
+
+    archive.read_entry(name, &mut |reader| {
+        // one byte past the limit tells an entry that is too large from one that just fits
+        let mut reader = reader.take(max_len.saturating_add(1));
+        let mut buf = vec![];
+        (&mut reader).take(MEMORY_LIMIT + 1).read_to_end(&mut buf)?;
+
+        let len = buf.len() as u64;
+
+        if len > max_len {
+            return Err(spool_too_large(name, max_len));
+        }
+
+        if len <= MEMORY_LIMIT {
+            res = Some((Box::new(Cursor::new(buf)), len));
+            return Ok(());
+        }
+
+        let mut file = TempFile::new()?;
+        file.file.write_all(&mem::take(&mut buf))?;
+        let rest = io::copy(&mut reader, &mut file.file)?;
+
+        if len + rest > max_len {
+            return Err(spool_too_large(name, max_len));
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+
+        res = Some((Box::new(file), len + rest));
+        Ok(())
+    })?;
+
+    res.ok_or_else(|| entry_not_found(name))
+}
+
+/// A scratch file that is removed once dropped.
+struct TempFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new() -> Result<Self> {
+        let path = temp_sibling(&env::temp_dir().join("archive"))?;
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        Ok(Self { file, path })
+    }
+}
+
+impl Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for TempFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lists the entries, filtered and sorted as asked. Size and modified time sort largest and newest first.
+pub fn list(
+    cache: &Cache,
+    path: &str,
+    container: Option<&str>,
+    options: &ListOptions,
+) -> Result<Vec<Entry>> {
+    let mut res = cache.with(path, container, |archive| archive.entries())?;
+
+    if options.skip_dirs {
+        res.retain(|it| !it.is_dir);
+    }
+
+    if let Some(category) = options.category {
+        res.retain(|it| it.category == Some(category));
+    }
+
+    if let Some(glob) = &options.glob {
+        let matcher = GlobBuilder::new(glob)
+            .case_insensitive(true)
+            .build()?
+            .compile_matcher();
+
+        res.retain(|it| matcher.is_match(&it.name));
+    }
+
+    match options.sort {
+        Some(Sort::Natural) => res.sort_by(|a, b| natural_cmp(&a.name, &b.name)),
+        Some(Sort::Name) => res.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some(Sort::Size) => res.sort_by_key(|it| Reverse(it.size)),
+        Some(Sort::Modified) => res.sort_by_key(|it| Reverse(it.modified_time)),
+        None => {}
+    }
+
+    Ok(res)
+}
+
+pub fn entry_not_found(name: &str) -> Error {
+    Error::new(format!("entry not found: {name}")).with_status(StatusCode::NOT_FOUND)
+}
+
+fn spool_too_large(name: &str, max_len: u64) -> Error {
+    Error::new(format!(
+        "inner archive {name} is too large to open, the limit is {} MiB",
+        max_len / 1024 / 1024
+    ))
+    .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+}
@@ -0,0 +1,179 @@
+use crate::archive::{Container, Entry, Source, entry_not_found};
+use crate::error::Result;
+use sevenz_rust2::{Archive, ArchiveEntry, BlockDecoder, EncoderMethod, Password};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const UNIX_EXTENSION: u32 = 0x8000;
+
+pub struct SevenZip {
+    archive: Archive,
+    source: Box<dyn Source>,
+    password: Password,
+    thread_count: u32,
+}
+
+impl SevenZip {
+    pub fn new(mut source: Box<dyn Source>) -> Result<Self> {
+        let password = Password::empty();
+        let archive = Archive::read(&mut source, &password)?;
+        let thread_count = std::thread::available_parallelism().map_or(1, |it| it.get() as u32);
+
+        Ok(Self {
+            archive,
+            source,
+            password,
+            thread_count,
+        })
+    }
+
+    fn decoder(&mut self, block: usize) -> BlockDecoder<'_, Box<dyn Source>> {
+        BlockDecoder::new(
+            self.thread_count,
+            block,
+            &self.archive,
+            &self.password,
+            &mut self.source,
+        )
+    }
+}
+
+impl Container for SevenZip {
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        let blocks = &self.archive.stream_map.file_block_index;
+
+        let res = self
+            .archive
+            .files
+            .iter()
+            .zip(blocks)
+            .map(|(file, block)| {
+                // methods are per block, a coder chain like BCJ + LZMA2 is reported as a whole
+                let methods: Vec<_> = match block {
+                    Some(block) if file.has_stream => self.archive.blocks[*block]
+                        .coders
+                        .iter()
+                        .filter_map(|it| EncoderMethod::by_id(it.encoder_method_id()))
+                        .collect(),
+                    _ => vec![],
+                };
+
+                entry(file, &methods)
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    fn read_entry(
+        &mut self,
+        name: &str,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        let index = self
+            .archive
+            .files
+            .iter()
+            .position(|it| it.name == name)
+            .ok_or_else(|| entry_not_found(name))?;
+
+        // empty files and directories have no data to decode
+        let Some(block) = self.archive.stream_map.file_block_index[index] else {
+            return f(&mut io::empty());
+        };
+
+        let target: *const ArchiveEntry = &self.archive.files[index];
+        let mut res = None;
+
+        // only the entry's own block is decoded and streamed through. a solid
+        // block holds other entries too, the ones before it are drained
+        self.decoder(block).for_each_entries(&mut |entry, reader| {
+            if !std::ptr::eq(entry, target) {
+                io::copy(reader, &mut io::sink())?;
+                return Ok(true);
+            }
+
+            res = Some(f(reader));
+            Ok(false)
+        })?;
+
+        res.unwrap_or_else(|| Err(entry_not_found(name)))
+    }
+
+    fn walk(&mut self, f: &mut dyn FnMut(&Entry, &mut dyn Read) -> Result<()>) -> Result<()> {
+        let mut entries: HashMap<_, _> = self
+            .entries()?
+            .into_iter()
+            .map(|it| (it.name.clone(), it))
+            .collect();
+        let mut res = Ok(());
+
+        let mut each = |file: &ArchiveEntry, reader: &mut dyn Read| {
+            // entries skipped here still have to be read past to reach the next one
+            let entry = match entries.remove(&file.name) {
+                Some(entry) if !file.is_anti_item => entry,
+                _ => {
+                    io::copy(reader, &mut io::sink())?;
+                    return Ok(true);
+                }
+            };
+
+            res = f(&entry, reader);
+
+            // whatever the callback left unread has to be drained to reach the next entry
+            io::copy(reader, &mut io::sink())?;
+            Ok(res.is_ok())
+        };
+
+        // blocks are decoded in order, empty files and directories come last
+        for block in 0..self.archive.blocks.len() {
+            if !self.decoder(block).for_each_entries(&mut each)? {
+                return res;
+            }
+        }
+
+        let blocks = &self.archive.stream_map.file_block_index;
+
+        for (file, _) in self
+            .archive
+            .files
+            .iter()
+            .zip(blocks)
+            .filter(|it| it.1.is_none())
+        {
+            if !each(file, &mut io::empty())? {
+                break;
+            }
+        }
+
+        res
+    }
+}
+
+fn entry(file: &ArchiveEntry, methods: &[EncoderMethod]) -> Entry {
+    let mut entry = Entry::new(file.name.clone(), file.size, file.is_directory);
+
+    entry.compressed_size = (file.compressed_size > 0).then_some(file.compressed_size);
+    entry.modified_time = file
+        .has_last_modified_date
+        .then(|| SystemTime::from(file.last_modified_date))
+        .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+        .map(|it| it.as_millis() as u64);
+    entry.crc = file.has_crc.then_some(file.crc as u32);
+    entry.encrypted = methods.contains(&EncoderMethod::AES256_SHA256);
+    entry.compression = (!methods.is_empty()).then(|| {
+        methods
+            .iter()
+            .map(|it| it.name())
+            .collect::<Vec<_>>()
+            .join("+")
+    });
+
+    // p7zip keeps the unix mode in the high bits of the attributes
+    if file.has_windows_attributes && file.windows_attributes & UNIX_EXTENSION != 0 {
+        entry.mode = Some(file.windows_attributes >> 16);
+    }
+
+    entry
+}
@@ -0,0 +1,128 @@
+use crate::archive::{Container, Entry, Source, entry_not_found};
+use crate::error::Result;
+use flate2::read::MultiGzDecoder;
+use liblzma::read::XzDecoder;
+use std::io::{BufReader, Read, SeekFrom};
+use std::path::Path;
+use tar::Archive;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gzip"),
+            Self::Xz => Some("xz"),
+            Self::Zstd => Some("zstd"),
+        }
+    }
+}
+
+/// Tar has no index so every call decodes the archive from the start.
+pub struct Tar {
+    source: Box<dyn Source>,
+    compression: Compression,
+}
+
+impl Tar {
+    pub fn new(source: Box<dyn Source>, compression: Compression) -> Self {
+        Self {
+            source,
+            compression,
+        }
+    }
+
+    fn archive(&mut self) -> Result<Archive<Box<dyn Read + '_>>> {
+        self.source.seek(SeekFrom::Start(0))?;
+        let source = BufReader::new(&mut self.source);
+
+        let reader: Box<dyn Read> = match self.compression {
+            Compression::None => Box::new(source),
+            Compression::Gzip => Box::new(MultiGzDecoder::new(source)),
+            Compression::Xz => Box::new(XzDecoder::new(source)),
+            Compression::Zstd => Box::new(zstd::Decoder::with_buffer(source)?),
+        };
+
+        Ok(Archive::new(reader))
+    }
+}
+
+impl Container for Tar {
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        let mut res = vec![];
+
+        let compression = self.compression;
+
+        for entry in self.archive()?.entries()? {
+            if let Some(entry) = entry_info(&entry?, compression)? {
+                res.push(entry);
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn read_entry(
+        &mut self,
+        name: &str,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        for entry in self.archive()?.entries()? {
+            let mut entry = entry?;
+
+            if entry_name(&entry.path()?) == name {
+                return f(&mut entry);
+            }
+        }
+
+        Err(entry_not_found(name))
+    }
+
+    fn walk(&mut self, f: &mut dyn FnMut(&Entry, &mut dyn Read) -> Result<()>) -> Result<()> {
+        let compression = self.compression;
+
+        for entry in self.archive()?.entries()? {
+            let mut entry = entry?;
+            let kind = entry.header().entry_type();
+
+            if !kind.is_file() && !kind.is_dir() {
+                continue;
+            }
+
+            if let Some(item) = entry_info(&entry, compression)? {
+                f(&item, &mut entry)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// archives created with `tar -C dir .` prefix every entry with ./
+fn entry_name(path: &Path) -> String {
+    let name = path.to_string_lossy();
+    name.strip_prefix("./").unwrap_or(&name).to_string()
+}
+
+fn entry_info(entry: &tar::Entry<impl Read>, compression: Compression) -> Result<Option<Entry>> {
+    let header = entry.header();
+    let name = entry_name(&entry.path()?);
+
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    let mut item = Entry::new(name, entry.size(), header.entry_type().is_dir());
+    item.modified_time = header.mtime().ok().map(|it| it * 1000);
+    item.mode = header.mode().ok();
+    item.compression = compression.name().map(|it| it.to_string());
+
+    Ok(Some(item))
+}
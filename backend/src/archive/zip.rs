@@ -0,0 +1,134 @@
+use crate::archive::{Container, Entry, Source, entry_not_found};
+use crate::error::Result;
+use chrono::{Local, NaiveDate, TimeZone};
+use std::io::{self, Read, Seek, SeekFrom};
+use zip::read::ZipFile;
+use zip::result::ZipError;
+use zip::{DateTime, ZipArchive};
+
+pub struct Zip {
+    archive: ZipArchive<Box<dyn Source>>,
+}
+
+impl Zip {
+    pub fn new(source: Box<dyn Source>) -> Result<Self> {
+        let archive = ZipArchive::new(source)?;
+
+        Ok(Self { archive })
+    }
+
+    fn index(&self, name: &str) -> Result<usize> {
+        self.archive
+            .index_for_name(name)
+            .ok_or_else(|| entry_not_found(name))
+    }
+}
+
+impl Container for Zip {
+    fn entries(&mut self) -> Result<Vec<Entry>> {
+        let mut res = Vec::with_capacity(self.archive.len());
+
+        for i in 0..self.archive.len() {
+            // raw access reads the metadata without needing a password for encrypted entries
+            res.push(entry(&self.archive.by_index_raw(i)?));
+        }
+
+        Ok(res)
+    }
+
+    fn read_entry(
+        &mut self,
+        name: &str,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        let mut file = self.archive.by_name(name).map_err(|err| match err {
+            ZipError::FileNotFound => entry_not_found(name),
+            err => err.into(),
+        })?;
+
+        f(&mut file)
+    }
+
+    fn read_entry_at(
+        &mut self,
+        name: &str,
+        offset: u64,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        let index = self.index(name)?;
+
+        // stored entries are plain bytes in the file and can be seeked into, the
+        // rest (and encrypted ones, which need decrypting) are decoded and skipped
+        if !self.archive.by_index_raw(index)?.encrypted() {
+            match self.archive.by_index_seek(index) {
+                Ok(mut file) => {
+                    file.seek(SeekFrom::Start(offset))?;
+                    return f(&mut file);
+                }
+                Err(ZipError::UnsupportedArchive(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut file = self.archive.by_index(index)?;
+        io::copy(&mut (&mut file).take(offset), &mut io::sink())?;
+
+        f(&mut file)
+    }
+
+    fn entry(&mut self, name: &str) -> Result<Entry> {
+        let index = self.index(name)?;
+        Ok(entry(&self.archive.by_index_raw(index)?))
+    }
+
+    fn walk(&mut self, f: &mut dyn FnMut(&Entry, &mut dyn Read) -> Result<()>) -> Result<()> {
+        for i in 0..self.archive.len() {
+            let (entry, is_symlink) = {
+                let file = self.archive.by_index_raw(i)?;
+                (entry(&file), file.is_symlink())
+            };
+
+            if is_symlink {
+                continue;
+            }
+
+            // encrypted entries can't be decoded, the callback decides what to do with them
+            if entry.encrypted {
+                f(&entry, &mut io::empty())?;
+            } else {
+                f(&entry, &mut self.archive.by_index(i)?)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn entry(file: &ZipFile<Box<dyn Source>>) -> Entry {
+    let mut entry = Entry::new(file.name().to_string(), file.size(), file.is_dir());
+
+    entry.compressed_size = Some(file.compressed_size());
+    entry.modified_time = file.last_modified().and_then(to_millis);
+    entry.mode = file.unix_mode();
+    entry.compression = Some(file.compression().to_string());
+    entry.crc = Some(file.crc32());
+    entry.encrypted = file.encrypted();
+
+    entry
+}
+
+// zip stores the local time of the machine that created it without an offset
+fn to_millis(time: DateTime) -> Option<u64> {
+    let time = NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+        .and_hms_opt(
+            time.hour().into(),
+            time.minute().into(),
+            time.second().into(),
+        )?;
+
+    let millis = Local
+        .from_local_datetime(&time)
+        .earliest()?
+        .timestamp_millis();
+    millis.try_into().ok()
+}
@@ -1,8 +1,13 @@
 use crate::error::{Error, Result};
+use crate::process::Env;
+use crate::schedule::Task;
+use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use serde_json::from_str as json_from_str;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs};
 
 impl Config {
@@ -51,6 +56,16 @@ pub struct Config {
     pub port: u16,
     pub db: Db,
     pub vars: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub commands: Commands,
+    #[serde(default)]
+    pub downloads: Downloads,
+    #[serde(default)]
+    pub jobs: Jobs,
+    #[serde(default)]
+    pub schedule: Schedule,
 
     #[serde(skip)]
     pub meta: Meta,
@@ -78,3 +93,182 @@ pub struct Mongo {
 pub struct Sqlite {
     pub path: String,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Cache {
+    /// Where generated files like thumbnails are kept, the system temp directory when not set.
+    pub dir: Option<PathBuf>,
+    /// How many parsed archives are kept open, 0 turns it off.
+    pub archives: Option<usize>,
+    /// How many bytes of nested archives read out of their parents the kept
+    /// ones may hold between them.
+    pub archive_bytes: Option<u64>,
+    /// How big a nested archive may get when it's read out of its parent to
+    /// be opened, bigger ones are refused.
+    pub spool_bytes: Option<u64>,
+    /// How many bytes of generated thumbnails are kept, the oldest ones are
+    /// removed past that.
+    pub thumbnail_bytes: Option<u64>,
+}
+
+impl Cache {
+    pub fn archives(&self) -> usize {
+        self.archives.unwrap_or(16)
+    }
+
+    pub fn archive_bytes(&self) -> u64 {
+        self.archive_bytes.unwrap_or(256 * 1024 * 1024)
+    }
+
+    pub fn spool_bytes(&self) -> u64 {
+        self.spool_bytes.unwrap_or(4 * 1024 * 1024 * 1024)
+    }
+
+    pub fn thumbnail_bytes(&self) -> u64 {
+        self.thumbnail_bytes.unwrap_or(256 * 1024 * 1024)
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| env::temp_dir().join(env!("CARGO_PKG_NAME")))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Jobs {
+    /// Seconds a finished job stays listed.
+    pub retention: Option<u64>,
+}
+
+impl Jobs {
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention.unwrap_or(60 * 60))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Downloads {
+    /// Where the queue is saved, `downloads.json` next to the config file when not set.
+    pub state: Option<PathBuf>,
+    pub concurrency: Option<usize>,
+    pub per_host: Option<usize>,
+    /// Bytes per second for each host, unlimited when not set.
+    pub rate: Option<u64>,
+    pub retries: Option<u32>,
+}
+
+impl Downloads {
+    pub fn state(&self, config_path: &Path) -> PathBuf {
+        self.state
+            .clone()
+            .unwrap_or_else(|| config_path.with_file_name("downloads.json"))
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(4).max(1)
+    }
+
+    pub fn per_host(&self) -> usize {
+        self.per_host.unwrap_or(2).max(1)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(3)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Schedule {
+    /// Where run history and tasks added at runtime are saved, `schedule.json`
+    /// next to the config file when not set.
+    pub state: Option<PathBuf>,
+    /// Runs kept per task.
+    pub history: Option<usize>,
+    #[serde(default)]
+    pub tasks: BTreeMap<String, Task>,
+}
+
+impl Schedule {
+    pub fn state(&self, config_path: &Path) -> PathBuf {
+        self.state
+            .clone()
+            .unwrap_or_else(|| config_path.with_file_name("schedule.json"))
+    }
+
+    pub fn history(&self) -> usize {
+        self.history.unwrap_or(50)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Commands {
+    /// Only presets can be run, the process bridge refuses arbitrary commands
+    /// and terminals can't be started.
+    #[serde(default)]
+    pub presets_only: bool,
+    #[serde(default)]
+    pub presets: BTreeMap<String, Preset>,
+}
+
+/// A named command, `args` may reference `params` as `{name}`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub program: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, Param>,
+    pub cwd: Option<PathBuf>,
+    pub env: Option<Env>,
+    pub timeout_ms: Option<u64>,
+    pub max_output_bytes: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Param {
+    #[serde(rename = "type")]
+    pub kind: ParamKind,
+    pub description: Option<String>,
+    pub default: Option<JsonValue>,
+    /// Arguments referencing it are left out when it has no value.
+    #[serde(default)]
+    pub optional: bool,
+    /// The only values accepted, any value of the type when empty.
+    #[serde(default)]
+    pub choices: Vec<JsonValue>,
+    /// Lets a string value start with `-`, which the program could take for an option.
+    #[serde(default)]
+    pub allow_dash: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamKind {
+    String,
+    Integer,
+    Number,
+    /// Expands to nothing when true, arguments referencing it are left out when false.
+    Boolean,
+    /// An absolute path.
+    Path,
+}
+
+impl Commands {
+    /// Fails when only presets may be run.
+    pub fn allow_arbitrary(&self) -> Result<()> {
+        if self.presets_only {
+            return Err(
+                Error::new("only command presets are allowed").with_status(StatusCode::FORBIDDEN)
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -129,22 +129,20 @@ impl Client {
         let mut res = Vec::with_capacity(data.len());
 
         for item in data {
-            if let Ok(mut item) = item.to_document() {
-                if let Some(id) = item.remove(PRIMARY_KEY).and_then(|id| id.as_object_id()) {
-                    if let Some(item) = coll
-                        .update_one(doc! { PRIMARY_KEY: id }, doc! { "$set": &item })
-                        .await
-                        .ok()
-                        .and_then(|r| {
-                            (r.modified_count > 0).then(|| {
-                                item.insert(PRIMARY_KEY, id.to_hex());
-                                item.to_json().ok()
-                            })?
-                        })
-                    {
-                        res.push(item);
-                    }
-                }
+            if let Ok(mut item) = item.to_document()
+                && let Some(id) = item.remove(PRIMARY_KEY).and_then(|id| id.as_object_id())
+                && let Some(item) = coll
+                    .update_one(doc! { PRIMARY_KEY: id }, doc! { "$set": &item })
+                    .await
+                    .ok()
+                    .and_then(|r| {
+                        (r.modified_count > 0).then(|| {
+                            item.insert(PRIMARY_KEY, id.to_hex());
+                            item.to_json().ok()
+                        })?
+                    })
+            {
+                res.push(item);
             }
         }
 
@@ -159,17 +157,15 @@ impl Client {
         let mut res = Vec::with_capacity(data.len());
 
         for item in data {
-            if let Ok(item) = item.to_document() {
-                if let Some(id) = item.get(PRIMARY_KEY).and_then(|id| id.as_object_id()) {
-                    if let Some(item) = coll
-                        .delete_one(doc! { PRIMARY_KEY: id })
-                        .await
-                        .ok()
-                        .and_then(|r| (r.deleted_count > 0).then(|| item.to_json().ok())?)
-                    {
-                        res.push(item);
-                    }
-                }
+            if let Ok(item) = item.to_document()
+                && let Some(id) = item.get(PRIMARY_KEY).and_then(|id| id.as_object_id())
+                && let Some(item) = coll
+                    .delete_one(doc! { PRIMARY_KEY: id })
+                    .await
+                    .ok()
+                    .and_then(|r| (r.deleted_count > 0).then(|| item.to_json().ok())?)
+            {
+                res.push(item);
             }
         }
 
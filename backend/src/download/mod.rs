@@ -0,0 +1,266 @@
+pub mod queue;
+
+use crate::download::queue::Limiter;
+use crate::error::{Error, Result};
+use crate::hash::{Algorithm, hash_file};
+use crate::jobs::JobHandle;
+use axum::http::StatusCode;
+use reqwest::Client as HttpClient;
+use reqwest::header::{CONTENT_RANGE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::task::spawn_blocking;
+use tokio::time::timeout;
+
+/// How often the speed is measured and progress reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A connection that goes quiet for this long fails the attempt, it can be resumed.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Checksum {
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadOptions {
+    pub checksum: Option<Checksum>,
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadReport {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Bytes that were already on disk from an earlier attempt.
+    pub resumed_from: u64,
+    pub checksum: Option<String>,
+}
+
+/// Streams `url` into `<path>.part` and renames it once complete. A `.part`
+/// file left by a cancelled or failed attempt is continued with a `Range`
+/// request when the server supports it, guarded by the validator it was
+/// started with so a changed file is downloaded again from the start.
+pub async fn download(
+    http_client: &HttpClient,
+    url: &str,
+    path: &Path,
+    options: &DownloadOptions,
+    limiter: Option<&Limiter>,
+    job: &JobHandle,
+) -> Result<DownloadReport> {
+    if !options.overwrite && fs::try_exists(path).await? {
+        return Err(Error::new(format!("already exists: {}", path.display()))
+            .with_status(StatusCode::CONFLICT));
+    }
+
+    let part = part_path(path);
+    let validator_path = validator_path(path);
+
+    // without a validator there's no telling the bytes on disk are from the same file
+    let validator = fs::read_to_string(&validator_path).await.ok();
+    let existing = match (fs::metadata(&part).await, &validator) {
+        (Ok(meta), Some(_)) => meta.len(),
+        _ => 0,
+    };
+
+    let mut req = http_client.get(url);
+
+    if existing > 0
+        && let Some(validator) = &validator
+    {
+        req = req
+            .header(RANGE, format!("bytes={existing}-"))
+            .header(IF_RANGE, validator);
+    }
+
+    let mut res = req.send().await?;
+    let status = res.status();
+
+    let offset = match status {
+        StatusCode::PARTIAL_CONTENT if existing > 0 => {
+            let start = res
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|it| it.to_str().ok())
+                .and_then(range_start);
+
+            if start != Some(existing) {
+                return Err(Error::new(format!("unexpected content range: {url}")));
+            }
+
+            existing
+        }
+        // the part file already holds everything
+        StatusCode::RANGE_NOT_SATISFIABLE if existing > 0 && total_size(&res) == Some(existing) => {
+            existing
+        }
+        // the server ignored the range, start over
+        _ if status.is_success() => 0,
+        _ => {
+            return Err(Error::new(format!("download failed: {status}: {url}"))
+                .with_status(StatusCode::BAD_GATEWAY));
+        }
+    };
+
+    let total = match status {
+        StatusCode::RANGE_NOT_SATISFIABLE => Some(existing),
+        _ => res.content_length().map(|it| it + offset),
+    };
+
+    if let (Some(expected), Some(total)) = (options.size, total)
+        && expected != total
+    {
+        return Err(Error::new(format!(
+            "size mismatch: expected {expected}, got {total}: {url}"
+        )));
+    }
+
+    let mut file = match offset {
+        0 => {
+            match response_validator(res.headers()) {
+                Some(validator) => fs::write(&validator_path, validator).await?,
+                None => fs::remove_file(&validator_path).await.unwrap_or_default(),
+            }
+
+            File::create(&part).await?
+        }
+        _ => OpenOptions::new().append(true).open(&part).await?,
+    };
+
+    let mut done = offset;
+    let mut last = (Instant::now(), done);
+    job.transfer(done, total, 0);
+
+    if status != StatusCode::RANGE_NOT_SATISFIABLE {
+        // the part file is kept on cancel so the next attempt can resume
+        loop {
+            let chunk = tokio::select! {
+                chunk = timeout(READ_TIMEOUT, res.chunk()) => chunk
+                    .map_err(|_| Error::new(format!("read timed out: {url}")))??,
+                _ = job.token.cancelled() => return Err(Error::new("cancelled")),
+            };
+
+            let Some(chunk) = chunk else {
+                break;
+            };
+
+            if let Some(limiter) = limiter {
+                limiter.acquire(chunk.len() as u64).await;
+            }
+
+            file.write_all(&chunk).await?;
+            done += chunk.len() as u64;
+
+            let elapsed = last.0.elapsed();
+
+            if elapsed >= PROGRESS_INTERVAL {
+                let speed = ((done - last.1) as f64 / elapsed.as_secs_f64()) as u64;
+                job.transfer(done, total, speed);
+                last = (Instant::now(), done);
+            }
+        }
+    }
+
+    file.sync_all().await?;
+    drop(file);
+
+    let expected = options.size.or(total);
+
+    if let Some(expected) = expected
+        && expected != done
+    {
+        // a short file can still be resumed, a longer one is broken
+        if done > expected {
+            remove_part(path);
+        }
+
+        return Err(Error::new(format!(
+            "size mismatch: expected {expected}, got {done}: {url}"
+        )));
+    }
+
+    let checksum = match &options.checksum {
+        Some(checksum) => {
+            let (src, algorithm) = (part.clone(), checksum.algorithm);
+            let actual = spawn_blocking(move || hash_file(&src, algorithm, |_| Ok(()))).await??;
+
+            // a corrupt file can't be resumed into a good one
+            if !actual.eq_ignore_ascii_case(checksum.value.trim()) {
+                remove_part(path);
+                return Err(Error::new(format!("checksum mismatch: {url}")));
+            }
+
+            Some(actual)
+        }
+        None => None,
+    };
+
+    fs::rename(&part, path).await?;
+    fs::remove_file(&validator_path).await.unwrap_or_default();
+    job.transfer(done, Some(done), 0);
+
+    Ok(DownloadReport {
+        path: path.to_path_buf(),
+        size: done,
+        resumed_from: offset,
+        checksum,
+    })
+}
+
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(OsString::from(".part"));
+
+    PathBuf::from(name)
+}
+
+/// Holds the `ETag` or `Last-Modified` the part file was started with.
+fn validator_path(path: &Path) -> PathBuf {
+    let mut name = part_path(path).into_os_string();
+    name.push(OsString::from(".validator"));
+
+    PathBuf::from(name)
+}
+
+/// Removes what an unfinished download left behind.
+pub fn remove_part(path: &Path) {
+    std::fs::remove_file(part_path(path)).ok();
+    std::fs::remove_file(validator_path(path)).ok();
+}
+
+// weak etags can't be used with If-Range, the date is the fallback
+fn response_validator(headers: &HeaderMap) -> Option<String> {
+    let etag = headers
+        .get(ETAG)
+        .and_then(|it| it.to_str().ok())
+        .filter(|it| !it.starts_with("W/"));
+
+    etag.or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())
+        .map(|it| it.to_string())
+}
+
+// bytes 100-199/200
+fn range_start(value: &str) -> Option<u64> {
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+// bytes */200, sent with 416
+fn total_size(res: &reqwest::Response) -> Option<u64> {
+    let value = res.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (_, total) = value.rsplit_once('/')?;
+
+    total.trim().parse().ok()
+}
@@ -0,0 +1,562 @@
+use crate::config::Downloads as QueueOptions;
+use crate::download::{DownloadOptions, download, remove_part};
+use crate::error::{Error, Result};
+use crate::fs::{WriteOptions, read_state, write_file};
+use crate::jobs::{Jobs, Progress, now};
+use axum::http::StatusCode;
+use reqwest::{Client as HttpClient, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{to_value as to_json, to_vec_pretty as to_json_vec};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time::{sleep, sleep_until};
+
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub id: u64,
+    pub url: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub options: DownloadOptions,
+    /// Higher goes first, ties go by the order they were added.
+    #[serde(default)]
+    pub priority: i32,
+    pub status: Status,
+    #[serde(default)]
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub added_time: u64,
+    /// When a failed attempt is retried.
+    pub retry_time: Option<u64>,
+    pub finished_time: Option<u64>,
+    pub job_id: Option<u64>,
+    /// Taken from the job while running.
+    #[serde(skip_deserializing)]
+    pub progress: Option<Progress>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Enqueue {
+    pub url: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub options: DownloadOptions,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Saved {
+    next_id: u64,
+    items: BTreeMap<u64, Item>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    saved: Saved,
+    limiters: HashMap<String, Arc<Limiter>>,
+}
+
+/// Runs queued downloads a few at a time, per host and overall. The queue is
+/// saved on every change and picked up again on start, downloads that were
+/// running continue from their part files.
+#[derive(Debug, Clone)]
+pub struct Queue {
+    inner: Arc<Mutex<Inner>>,
+    notify: Arc<Notify>,
+    path: PathBuf,
+    options: QueueOptions,
+    http_client: HttpClient,
+    jobs: Jobs,
+}
+
+impl Queue {
+    pub fn new(
+        path: PathBuf,
+        options: QueueOptions,
+        http_client: HttpClient,
+        jobs: Jobs,
+    ) -> Result<Self> {
+        let mut saved: Saved = read_state(&path, "downloads");
+
+        for item in saved.items.values_mut() {
+            item.job_id = None;
+
+            if item.status == Status::Running {
+                item.status = Status::Queued;
+            }
+        }
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                saved,
+                limiters: HashMap::new(),
+            })),
+            notify: Arc::default(),
+            path,
+            options,
+            http_client,
+            jobs,
+        })
+    }
+
+    /// Starts the loop that hands queued items to the job runner.
+    pub fn start(&self) {
+        let queue = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let wait = match queue.schedule() {
+                    Ok(wait) => wait,
+                    Err(err) => {
+                        eprintln!("[downloads] {}", err.message);
+                        Some(RETRY_DELAY)
+                    }
+                };
+
+                match wait {
+                    Some(wait) => {
+                        tokio::select! {
+                            _ = queue.notify.notified() => {}
+                            _ = sleep(wait) => {}
+                        }
+                    }
+                    None => queue.notify.notified().await,
+                }
+            }
+        });
+    }
+
+    /// Adds downloads to the queue. A path an unfinished item is already
+    /// downloading to is refused, both would write the same part file.
+    pub fn enqueue(&self, items: Vec<Enqueue>) -> Result<Vec<Item>> {
+        let mut res = vec![];
+
+        {
+            let mut inner = self.inner.lock()?;
+
+            for (index, it) in items.iter().enumerate() {
+                host(&it.url)?;
+
+                let taken = inner.saved.items.values().any(|item| {
+                    item.path == it.path
+                        && (item.job_id.is_some()
+                            || matches!(
+                                item.status,
+                                Status::Queued | Status::Running | Status::Paused
+                            ))
+                });
+
+                if taken || items[..index].iter().any(|other| other.path == it.path) {
+                    return Err(Error::new(format!(
+                        "already downloading to {}",
+                        it.path.display()
+                    ))
+                    .with_status(StatusCode::CONFLICT));
+                }
+            }
+
+            for it in items {
+                inner.saved.next_id += 1;
+                let id = inner.saved.next_id;
+
+                let item = Item {
+                    id,
+                    url: it.url,
+                    path: it.path,
+                    options: it.options,
+                    priority: it.priority,
+                    status: Status::Queued,
+                    attempts: 0,
+                    error: None,
+                    added_time: now(),
+                    retry_time: None,
+                    finished_time: None,
+                    job_id: None,
+                    progress: None,
+                };
+
+                inner.saved.items.insert(id, item.clone());
+                res.push(item);
+            }
+
+            self.save(&inner)?;
+        }
+
+        self.notify.notify_one();
+        Ok(res)
+    }
+
+    pub fn list(&self) -> Result<Vec<Item>> {
+        let inner = self.inner.lock()?;
+
+        Ok(inner
+            .saved
+            .items
+            .values()
+            .map(|item| {
+                let mut item = item.clone();
+                item.progress = item
+                    .job_id
+                    .and_then(|id| self.jobs.get(id).ok())
+                    .map(|job| job.progress);
+
+                item
+            })
+            .collect())
+    }
+
+    /// Stops a running download but keeps its part file for [`Queue::resume`].
+    pub fn pause(&self, id: u64) -> Result<Item> {
+        self.update(id, |item| match item.status {
+            Status::Queued | Status::Running => {
+                item.status = Status::Paused;
+                Ok(())
+            }
+            _ => Err(invalid_status(item)),
+        })
+    }
+
+    /// Puts a paused, failed or cancelled item back in the queue with its attempts reset.
+    pub fn resume(&self, id: u64) -> Result<Item> {
+        let item = self.update(id, |item| match item.status {
+            Status::Paused | Status::Failed | Status::Cancelled => {
+                item.status = Status::Queued;
+                item.attempts = 0;
+                item.error = None;
+                item.retry_time = None;
+                item.finished_time = None;
+                Ok(())
+            }
+            _ => Err(invalid_status(item)),
+        })?;
+
+        self.notify.notify_one();
+        Ok(item)
+    }
+
+    pub fn set_priority(&self, id: u64, priority: i32) -> Result<Item> {
+        self.update(id, |item| {
+            item.priority = priority;
+            Ok(())
+        })
+    }
+
+    /// Stops the download for good and removes its part file.
+    pub fn cancel(&self, id: u64) -> Result<Item> {
+        let item = self.update(id, |item| match item.status {
+            Status::Completed | Status::Cancelled => Err(invalid_status(item)),
+            _ => {
+                item.status = Status::Cancelled;
+                item.finished_time = Some(now());
+                Ok(())
+            }
+        })?;
+
+        // a running job removes it once it has stopped writing
+        if item.job_id.is_none() {
+            remove_part(&item.path);
+        }
+
+        Ok(item)
+    }
+
+    /// Drops finished items from the list, or only the ones given.
+    pub fn clear(&self, ids: Option<Vec<u64>>) -> Result<Vec<Item>> {
+        let mut inner = self.inner.lock()?;
+
+        let finished = |item: &Item| {
+            matches!(
+                item.status,
+                Status::Completed | Status::Failed | Status::Cancelled
+            )
+        };
+
+        let ids: Vec<u64> = match ids {
+            Some(ids) => ids,
+            None => inner.saved.items.keys().copied().collect(),
+        };
+
+        let mut res = vec![];
+
+        for id in ids {
+            if let Some(item) = inner.saved.items.get(&id)
+                && finished(item)
+            {
+                res.extend(inner.saved.items.remove(&id));
+            }
+        }
+
+        self.save(&inner)?;
+        Ok(res)
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Item) -> Result<()>) -> Result<Item> {
+        let mut inner = self.inner.lock()?;
+
+        let item = inner
+            .saved
+            .items
+            .get_mut(&id)
+            .ok_or_else(|| Error::new(format!("download not found: {id}")))?;
+
+        f(item)?;
+        let item = item.clone();
+
+        if let Some(job_id) = item.job_id
+            && item.status != Status::Running
+        {
+            self.jobs.cancel(job_id)?;
+        }
+
+        self.save(&inner)?;
+        Ok(item)
+    }
+
+    /// Starts whatever fits in the limits, returning how long until a retry is due.
+    fn schedule(&self) -> Result<Option<Duration>> {
+        let mut inner = self.inner.lock()?;
+        let time = now();
+
+        let running: Vec<&Item> = inner
+            .saved
+            .items
+            .values()
+            .filter(|it| it.status == Status::Running)
+            .collect();
+
+        let mut total = running.len();
+        let mut hosts: HashMap<String, usize> = HashMap::new();
+
+        // a resumed item waits for a job still writing to the same path
+        let mut paths: HashSet<PathBuf> = inner
+            .saved
+            .items
+            .values()
+            .filter(|it| it.job_id.is_some())
+            .map(|it| it.path.clone())
+            .collect();
+
+        for item in running {
+            *hosts
+                .entry(host(&item.url).unwrap_or_default())
+                .or_default() += 1;
+        }
+
+        let mut queued: Vec<(i32, u64)> = inner
+            .saved
+            .items
+            .values()
+            // a paused job that's still winding down holds on to its part file
+            .filter(|it| it.status == Status::Queued && it.job_id.is_none())
+            .map(|it| (it.priority, it.id))
+            .collect();
+
+        queued.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut wait: Option<u64> = None;
+        let mut started = false;
+
+        for (_, id) in queued {
+            if total >= self.options.concurrency() {
+                break;
+            }
+
+            let item = &inner.saved.items[&id];
+
+            if let Some(retry_time) = item.retry_time
+                && retry_time > time
+            {
+                let left = retry_time - time;
+                wait = Some(wait.map_or(left, |it| it.min(left)));
+                continue;
+            }
+
+            if paths.contains(&item.path) {
+                continue;
+            }
+
+            let host = host(&item.url).unwrap_or_default();
+            let count = hosts.entry(host.clone()).or_default();
+
+            if *count >= self.options.per_host() {
+                continue;
+            }
+
+            *count += 1;
+            total += 1;
+            paths.insert(item.path.clone());
+
+            let limiter = self.options.rate.map(|rate| {
+                inner
+                    .limiters
+                    .entry(host)
+                    .or_insert_with(|| Arc::new(Limiter::new(rate)))
+                    .clone()
+            });
+
+            let item = inner.saved.items.get_mut(&id).expect("queued item");
+            item.status = Status::Running;
+            item.attempts += 1;
+            item.retry_time = None;
+
+            let job = self.run(item.clone(), limiter)?;
+            item.job_id = Some(job);
+            started = true;
+        }
+
+        if started {
+            self.save(&inner)?;
+        }
+
+        Ok(wait.map(Duration::from_millis))
+    }
+
+    fn run(&self, item: Item, limiter: Option<Arc<Limiter>>) -> Result<u64> {
+        let queue = self.clone();
+
+        let job = self.jobs.spawn("download/queue", move |job| async move {
+            let res = download(
+                &queue.http_client,
+                &item.url,
+                &item.path,
+                &item.options,
+                limiter.as_deref(),
+                &job,
+            )
+            .await;
+
+            let res = match res {
+                Ok(report) => to_json(&report).map_err(Error::from),
+                Err(err) => Err(err),
+            };
+
+            queue.finish(item.id, res.as_ref().err());
+            res
+        })?;
+
+        Ok(job.id)
+    }
+
+    fn finish(&self, id: u64, err: Option<&Error>) {
+        let res = (|| {
+            let mut inner = self.inner.lock()?;
+
+            let Some(item) = inner.saved.items.get_mut(&id) else {
+                return Ok(());
+            };
+
+            item.job_id = None;
+
+            match (item.status, err) {
+                (Status::Running, None) => {
+                    item.status = Status::Completed;
+                    item.error = None;
+                    item.finished_time = Some(now());
+                }
+                (Status::Running, Some(err)) => {
+                    item.error = Some(err.message.clone());
+
+                    // a file that's already there won't go away by trying again
+                    if err.status == StatusCode::CONFLICT || item.attempts > self.options.retries()
+                    {
+                        item.status = Status::Failed;
+                        item.finished_time = Some(now());
+                    } else {
+                        let delay = RETRY_DELAY * 2u32.pow(item.attempts.min(16) - 1);
+                        item.status = Status::Queued;
+                        item.retry_time =
+                            Some(now() + delay.min(MAX_RETRY_DELAY).as_millis() as u64);
+                    }
+                }
+                (Status::Cancelled, _) => {
+                    remove_part(&item.path);
+                }
+                _ => {}
+            }
+
+            self.save(&inner)
+        })();
+
+        if let Err(err) = res {
+            eprintln!("[downloads] {}", err.message);
+        }
+
+        self.notify.notify_one();
+    }
+
+    fn save(&self, inner: &Inner) -> Result<()> {
+        let options = WriteOptions {
+            atomic: true,
+            create_parents: true,
+            ..Default::default()
+        };
+
+        write_file(&self.path, &to_json_vec(&inner.saved)?, &options)
+    }
+}
+
+/// Shares a byte rate between the downloads of one host.
+#[derive(Debug)]
+pub struct Limiter {
+    rate: u64,
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `len` more bytes fit in the rate.
+    pub async fn acquire(&self, len: u64) {
+        let start = {
+            let Ok(mut next) = self.next.lock() else {
+                return;
+            };
+
+            let start = (*next).max(Instant::now());
+            *next = start + Duration::from_secs_f64(len as f64 / self.rate as f64);
+
+            start
+        };
+
+        sleep_until(start.into()).await;
+    }
+}
+
+fn host(url: &str) -> Result<String> {
+    let url = Url::parse(url)?;
+
+    url.host_str()
+        .map(|it| it.to_lowercase())
+        .ok_or_else(|| Error::new(format!("invalid url: {url}")))
+}
+
+fn invalid_status(item: &Item) -> Error {
+    let status = format!("{:?}", item.status).to_lowercase();
+    Error::new(format!("download is {status}: {}", item.id)).with_status(StatusCode::CONFLICT)
+}
@@ -9,14 +9,23 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, Serialize)]
 pub struct Error {
     pub message: String,
+
+    #[serde(skip)]
+    pub status: StatusCode,
 }
 
 impl Error {
     pub fn new(message: impl Into<String>) -> Self {
         Self {
             message: message.into(),
+            status: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
 }
 
 impl<T: std::error::Error> From<T> for Error {
@@ -33,7 +42,7 @@ impl fmt::Display for Error {
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(self)).into_response()
+        (self.status, Json(self)).into_response()
     }
 }
 
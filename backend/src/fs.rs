@@ -1,61 +1,990 @@
+use crate::archive::cache::Cache;
+use crate::archive::{self, ListOptions};
 use crate::error::{Error, Result};
-use reqwest::Client as HttpClient;
-use serde::Serialize;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::time::UNIX_EPOCH;
-use zip::ZipArchive;
+use crate::jobs::JobHandle;
+use crate::mime::{self, Category};
+use axum::http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, FileTimes, Metadata, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{io, process};
 
 pub const CONTAINER_SEP: &str = ">";
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
     pub size: u64,
     pub is_dir: bool,
     pub is_file: bool,
+    pub is_symlink: bool,
+    pub symlink_target: Option<PathBuf>,
     pub modified_time: u64,
+    pub accessed_time: Option<u64>,
+    pub created_time: Option<u64>,
+    pub readonly: bool,
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    pub inode: Option<u64>,
+    pub nlink: Option<u64>,
+    pub mime: Option<String>,
+    pub category: Option<Category>,
 }
 
-pub fn stats(path: &Path) -> Result<Stats> {
-    let meta = path.metadata()?;
-    let modified_time: u64 = meta
-        .modified()?
-        .duration_since(UNIX_EPOCH)?
-        .as_millis()
-        .try_into()
-        .unwrap_or_default();
+/// Symlinks are reported as such along with their target, the remaining
+/// fields describe the target when it resolves and the link itself otherwise.
+/// The mime type comes from the extension unless `sniff` reads the file's head.
+pub fn stats(path: &Path, sniff: bool) -> Result<Stats> {
+    let link_meta = path.symlink_metadata()?;
+    let is_symlink = link_meta.is_symlink();
+    let symlink_target = is_symlink.then(|| fs::read_link(path)).transpose()?;
+
+    let meta = match is_symlink {
+        true => path.metadata().unwrap_or(link_meta),
+        false => link_meta,
+    };
 
-    Ok(Stats {
+    let mut res = Stats {
         size: meta.len(),
         is_file: meta.is_file(),
         is_dir: meta.is_dir(),
-        modified_time,
-    })
+        is_symlink,
+        symlink_target,
+        modified_time: millis(meta.modified()?)?,
+        accessed_time: meta.accessed().ok().and_then(|it| millis(it).ok()),
+        // some filesystems report a zero birth time instead of none
+        created_time: meta
+            .created()
+            .ok()
+            .and_then(|it| millis(it).ok())
+            .filter(|it| *it > 0),
+        readonly: meta.permissions().readonly(),
+        ..Default::default()
+    };
+
+    if meta.is_file() {
+        let file_type = match sniff {
+            true => mime::detect(path).ok(),
+            false => Some(mime::detect_name(&path.to_string_lossy())),
+        };
+
+        if let Some(file_type) = file_type {
+            res.mime = Some(file_type.mime);
+            res.category = Some(file_type.category);
+        }
+    }
+
+    unix_stats(&mut res, &meta);
+    Ok(res)
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub stats: Option<Stats>,
+}
+
+/// Lists the entry names, with their stats when `detailed` is set.
+/// Entries that can't be stat'ed are still listed without them.
+pub fn read_dir(path: &Path, detailed: bool) -> Result<Vec<DirEntry>> {
+    let mut res = vec![];
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+
+        res.push(DirEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            stats: detailed.then(|| stats(&entry.path(), false).ok()).flatten(),
+        });
+    }
+
+    Ok(res)
+}
+
+fn millis(time: SystemTime) -> Result<u64> {
+    Ok(time
+        .duration_since(UNIX_EPOCH)?
+        .as_millis()
+        .try_into()
+        .unwrap_or_default())
+}
+
+#[cfg(unix)]
+fn unix_stats(stats: &mut Stats, meta: &Metadata) {
+    use std::os::unix::fs::MetadataExt;
+
+    stats.mode = Some(meta.mode() & 0o7777);
+    stats.uid = Some(meta.uid());
+    stats.gid = Some(meta.gid());
+    stats.user = user_name(meta.uid());
+    stats.group = group_name(meta.gid());
+    stats.inode = Some(meta.ino());
+    stats.nlink = Some(meta.nlink());
+}
+
+#[cfg(not(unix))]
+fn unix_stats(_: &mut Stats, _: &Metadata) {}
+
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    let mut pwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+
+    lookup_name(
+        |buf, res| unsafe {
+            libc::getpwuid_r(uid, pwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), res)
+        },
+        |it| it.pw_name,
+    )
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    let mut grp = std::mem::MaybeUninit::<libc::group>::uninit();
+
+    lookup_name(
+        |buf, res| unsafe {
+            libc::getgrgid_r(gid, grp.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), res)
+        },
+        |it| it.gr_name,
+    )
+}
+
+// the *_r lookups report ERANGE until the scratch buffer is large enough,
+// the name points into that buffer so it has to be copied out before returning
+#[cfg(unix)]
+fn lookup_name<T>(
+    mut f: impl FnMut(&mut [libc::c_char], &mut *mut T) -> libc::c_int,
+    name: impl Fn(&T) -> *const libc::c_char,
+) -> Option<String> {
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let mut res = std::ptr::null_mut();
+
+        match f(&mut buf, &mut res) {
+            0 if !res.is_null() => {
+                let name = unsafe { std::ffi::CStr::from_ptr(name(&*res)) };
+                return Some(name.to_string_lossy().to_string());
+            }
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            _ => return None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Mode {
+    Bits(u32),
+    Octal(String),
+}
+
+impl Mode {
+    pub fn bits(&self) -> Result<u32> {
+        match self {
+            Self::Bits(bits) => Ok(*bits),
+            Self::Octal(value) => Ok(u32::from_str_radix(value.trim_start_matches("0o"), 8)?),
+        }
+    }
 }
 
-pub async fn download_file(http_client: &HttpClient, url: &str, path: &Path) -> Result<()> {
-    let res = http_client.get(url).send().await?;
-    let body = res.bytes().await?;
-    let mut buf = BufWriter::new(File::create(path)?);
+#[cfg(unix)]
+pub fn chmod(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(mode))?)
+}
+
+// only the write bits map to anything outside of unix
+#[cfg(not(unix))]
+pub fn chmod(path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = path.metadata()?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+
+    Ok(fs::set_permissions(path, permissions)?)
+}
+
+/// Sets the times from unix millis, leaving out the ones that aren't given.
+pub fn set_times(
+    path: &Path,
+    modified_time: Option<u64>,
+    accessed_time: Option<u64>,
+) -> Result<()> {
+    let from_millis = |it| UNIX_EPOCH + Duration::from_millis(it);
+    let mut times = FileTimes::new();
+
+    if let Some(time) = modified_time {
+        times = times.set_modified(from_millis(time));
+    }
+
+    if let Some(time) = accessed_time {
+        times = times.set_accessed(from_millis(time));
+    }
 
-    buf.write_all(&body)?;
-    buf.flush()?;
+    apply_times(path, &path.metadata()?, times)
+}
+
+fn apply_times(path: &Path, meta: &Metadata, times: FileTimes) -> Result<()> {
+    // directories can't be opened for writing
+    File::options()
+        .write(meta.is_file())
+        .read(!meta.is_file())
+        .open(path)?
+        .set_times(times)?;
 
     Ok(())
 }
 
-pub fn read_archive(path: &Path, container: &str) -> Result<Vec<String>> {
-    match container {
-        "zip" | "cbz" => {
-            let file = File::open(path)?;
-            let archive = ZipArchive::new(file)?;
-            let res = archive.file_names().map(|n| n.to_string()).collect();
+#[cfg(unix)]
+pub fn symlink(target: &Path, path: &Path) -> Result<()> {
+    Ok(std::os::unix::fs::symlink(target, path)?)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, path: &Path) -> Result<()> {
+    let resolved = path
+        .parent()
+        .map(|it| it.join(target))
+        .unwrap_or(target.into());
+
+    if resolved.is_dir() {
+        Ok(std::os::windows::fs::symlink_dir(target, path)?)
+    } else {
+        Ok(std::os::windows::fs::symlink_file(target, path)?)
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WriteMode {
+    #[default]
+    Overwrite,
+    Append,
+    CreateNew,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteOptions {
+    #[serde(default)]
+    pub mode: WriteMode,
+    #[serde(default)]
+    pub atomic: bool,
+    #[serde(default)]
+    pub create_parents: bool,
+    pub if_modified_time: Option<u64>,
+}
+
+/// Loads a JSON state file, a missing one starts empty. One that can't be
+/// parsed is moved aside to `<path>.corrupt` so saving doesn't overwrite it.
+pub fn read_state<T: DeserializeOwned + Default>(path: &Path, tag: &str) -> T {
+    let Ok(data) = fs::read(path) else {
+        return T::default();
+    };
+
+    match serde_json::from_slice(&data) {
+        Ok(state) => state,
+        Err(err) => {
+            let mut aside = path.as_os_str().to_os_string();
+            aside.push(".corrupt");
+
+            eprintln!(
+                "[{tag}] {}: {err}, moved to {}",
+                path.display(),
+                Path::new(&aside).display()
+            );
+
+            if let Err(err) = fs::rename(path, &aside) {
+                eprintln!("[{tag}] {}: {err}", path.display());
+            }
 
-            Ok(res)
+            T::default()
         }
+    }
+}
+
+pub fn write_file(path: &Path, data: &[u8], options: &WriteOptions) -> Result<()> {
+    if let Some(expected) = options.if_modified_time {
+        let current = match stats(path, false) {
+            Ok(it) => Some(it.modified_time),
+            Err(_) if !path.exists() => None,
+            Err(err) => return Err(err),
+        };
+
+        if current != Some(expected) {
+            return Err(
+                Error::new(format!("modified since last read: {}", path.display()))
+                    .with_status(StatusCode::CONFLICT),
+            );
+        }
+    }
+
+    if options.create_parents
+        && let Some(parent) = path.parent().filter(|it| !it.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    if options.atomic {
+        return write_file_atomic(path, data, options.mode);
+    }
+
+    let mut file = match options.mode {
+        WriteMode::Overwrite => File::create(path)?,
+        WriteMode::Append => OpenOptions::new().append(true).create(true).open(path)?,
+        WriteMode::CreateNew => OpenOptions::new().write(true).create_new(true).open(path)?,
+    };
+
+    file.write_all(data)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+// the data is written to a sibling temp file, synced and then swapped in
+// so readers only ever see the old or the new contents
+fn write_file_atomic(path: &Path, data: &[u8], mode: WriteMode) -> Result<()> {
+    let temp_path = temp_sibling(path)?;
+
+    let res = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        if let Ok(meta) = path.metadata() {
+            file.set_permissions(meta.permissions())?;
 
-        _ => Err(Error::new("unsupported container")),
+            if mode == WriteMode::Append {
+                io::copy(&mut File::open(path)?, &mut file)?;
+            }
+        }
+
+        file.write_all(data)?;
+        file.sync_all()?;
+
+        match mode {
+            // hard linking fails if the destination exists which keeps create-new race free
+            WriteMode::CreateNew => {
+                fs::hard_link(&temp_path, path).map_err(|err| match err.kind() {
+                    ErrorKind::AlreadyExists => {
+                        Error::new(format!("already exists: {}", path.display()))
+                            .with_status(StatusCode::CONFLICT)
+                    }
+                    _ => err.into(),
+                })?;
+                fs::remove_file(&temp_path)?;
+            }
+            _ => fs::rename(&temp_path, path)?,
+        }
+
+        sync_parent(path)
+    })();
+
+    if res.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    res
+}
+
+pub fn temp_sibling(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::new(format!("invalid path: {}", path.display())))?
+        .to_string_lossy();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+
+    Ok(path.with_file_name(format!(".{name}.{}.{nanos}.tmp", process::id())))
+}
+
+/// Appends a counter before the extension, `n == 1` keeps the name as is.
+pub fn unique_name(name: &OsStr, n: usize) -> OsString {
+    if n == 1 {
+        return name.to_os_string();
     }
+
+    let path = Path::new(name);
+    let mut res = path.file_stem().unwrap_or(name).to_os_string();
+    res.push(format!(".{n}"));
+
+    if let Some(ext) = path.extension() {
+        res.push(".");
+        res.push(ext);
+    }
+
+    res
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|it| !it.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyOptions {
+    #[serde(default)]
+    pub overwrite: bool,
+    #[serde(default)]
+    pub preserve_times: bool,
+    #[serde(default)]
+    pub preserve_permissions: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferFailure {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TransferReport {
+    pub files: u64,
+    pub bytes: u64,
+    pub failures: Vec<TransferFailure>,
+}
+
+struct Transfer<'a> {
+    job: &'a JobHandle,
+    options: &'a CopyOptions,
+    total: u64,
+    report: TransferReport,
+}
+
+/// Copies a file or a directory tree, recording per-file failures instead of aborting.
+pub fn copy(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    job: &JobHandle,
+) -> Result<TransferReport> {
+    let src_meta = src.symlink_metadata()?;
+
+    // compared with links and `..` resolved, a raw prefix check misses those
+    if src_meta.is_dir() && resolve_path(dst)?.starts_with(src.canonicalize()?) {
+        return Err(Error::new(format!(
+            "cannot copy a directory into itself: {}",
+            src.display()
+        )));
+    }
+
+    // overwriting would truncate the source before it's read
+    let same_id = match (src.metadata(), dst.metadata()) {
+        (Ok(src), Ok(dst)) => file_id(&src).is_some_and(|id| file_id(&dst) == Some(id)),
+        _ => false,
+    };
+
+    if same_id || entry_location(src)? == entry_location(dst)? {
+        return Err(Error::new(format!(
+            "source and destination are the same file: {}",
+            src.display()
+        )));
+    }
+
+    let mut transfer = Transfer {
+        job,
+        options,
+        total: tree_size(src, job)?.bytes,
+        report: TransferReport::default(),
+    };
+
+    transfer.copy_entry(src, dst)?;
+    Ok(transfer.report)
+}
+
+/// Renames when possible and falls back to copy + delete across devices.
+/// The source is only removed when every entry was copied successfully.
+pub fn move_entry(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    job: &JobHandle,
+) -> Result<TransferReport> {
+    if !options.overwrite && dst.symlink_metadata().is_ok() {
+        return Err(Error::new(format!("already exists: {}", dst.display())));
+    }
+
+    match fs::rename(src, dst) {
+        Ok(()) => {
+            let size = tree_size(dst, job)?;
+            job.progress(size.bytes, Some(size.bytes), None);
+
+            return Ok(TransferReport {
+                files: size.files,
+                bytes: size.bytes,
+                failures: vec![],
+            });
+        }
+        Err(err) if err.kind() != ErrorKind::CrossesDevices => return Err(err.into()),
+        Err(_) => {}
+    }
+
+    let options = CopyOptions {
+        overwrite: options.overwrite,
+        preserve_times: true,
+        preserve_permissions: true,
+    };
+    let report = copy(src, dst, &options, job)?;
+
+    if report.failures.is_empty() {
+        job.check()?;
+
+        if src.is_dir() {
+            fs::remove_dir_all(src)?;
+        } else {
+            fs::remove_file(src)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Resolves links and `..` in the part of `path` that exists, the rest is
+/// appended as is.
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut existing = path.as_path();
+    let mut rest = vec![];
+
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return Ok(rest.iter().rev().fold(real, |res, it| res.join(it)));
+        }
+
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return Err(Error::new(format!("invalid path: {}", path.display()))),
+        }
+    }
+}
+
+/// Where the entry itself lives, its parent resolved but a link at the end kept as is.
+fn entry_location(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::new(format!("invalid path: {}", path.display())))?;
+    let parent = path.parent().filter(|it| !it.as_os_str().is_empty());
+
+    Ok(resolve_path(parent.unwrap_or(Path::new(".")))?.join(name))
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TreeSize {
+    pub bytes: u64,
+    /// Counted like a copy counts them, links included and directories not.
+    pub files: u64,
+}
+
+pub fn tree_size(path: &Path, job: &JobHandle) -> Result<TreeSize> {
+    job.check()?;
+    let meta = path.symlink_metadata()?;
+
+    if !meta.is_dir() {
+        return Ok(TreeSize {
+            bytes: meta.len(),
+            files: 1,
+        });
+    }
+
+    let mut res = TreeSize::default();
+
+    for entry in fs::read_dir(path)? {
+        let child = tree_size(&entry?.path(), job).unwrap_or_default();
+
+        res.bytes += child.bytes;
+        res.files += child.files;
+    }
+
+    Ok(res)
+}
+
+impl Transfer<'_> {
+    fn copy_entry(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        self.job.check()?;
+
+        let res = (|| {
+            let meta = src.symlink_metadata()?;
+
+            if meta.is_dir() {
+                self.copy_dir(src, dst, &meta)
+            } else if meta.is_symlink() {
+                self.copy_symlink(src, dst)
+            } else {
+                self.copy_file(src, dst, &meta)
+            }
+        })();
+
+        match res {
+            Err(err) if self.job.is_cancelled() => Err(err),
+            Err(err) => {
+                self.report.failures.push(TransferFailure {
+                    path: src.to_path_buf(),
+                    message: err.message,
+                });
+
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn copy_dir(&mut self, src: &Path, dst: &Path, meta: &Metadata) -> Result<()> {
+        if !dst.is_dir() {
+            fs::create_dir(dst)?;
+        }
+
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            self.copy_entry(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+
+        // applied last since adding children updates the directory times
+        self.apply_metadata(dst, meta)
+    }
+
+    #[cfg(unix)]
+    fn copy_symlink(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        if dst.symlink_metadata().is_ok() {
+            if !self.options.overwrite {
+                return Err(Error::new(format!("already exists: {}", dst.display())));
+            }
+
+            fs::remove_file(dst)?;
+        }
+
+        std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+        self.report.files += 1;
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn copy_symlink(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        self.copy_file(src, dst, &src.metadata()?)
+    }
+
+    fn copy_file(&mut self, src: &Path, dst: &Path, meta: &Metadata) -> Result<()> {
+        let mut reader = File::open(src)?;
+        let mut writer = if self.options.overwrite {
+            File::create(dst)?
+        } else {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(dst)
+                .map_err(|err| match err.kind() {
+                    ErrorKind::AlreadyExists => {
+                        Error::new(format!("already exists: {}", dst.display()))
+                    }
+                    _ => err.into(),
+                })?
+        };
+
+        let message = Some(src.to_string_lossy().to_string());
+        let mut buf = vec![0u8; 64 * 1024];
+
+        loop {
+            self.job.check()?;
+
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            writer.write_all(&buf[..read])?;
+            self.report.bytes += read as u64;
+            self.job
+                .progress(self.report.bytes, Some(self.total), message.clone());
+        }
+
+        writer.sync_all()?;
+        drop(writer);
+
+        self.apply_metadata(dst, meta)?;
+        self.report.files += 1;
+
+        Ok(())
+    }
+
+    fn apply_metadata(&self, dst: &Path, meta: &Metadata) -> Result<()> {
+        // times go first as the copied permissions may not allow reopening for writing
+        if self.options.preserve_times {
+            let times = FileTimes::new()
+                .set_accessed(meta.accessed()?)
+                .set_modified(meta.modified()?);
+
+            apply_times(dst, meta, times)?;
+        }
+
+        if self.options.preserve_permissions {
+            fs::set_permissions(dst, meta.permissions())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskUsage {
+    pub path: PathBuf,
+    pub size: u64,
+    pub allocated_size: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub children: Vec<DiskUsage>,
+}
+
+/// Recursively sums the tree under `path`, keeping the per child breakdown
+/// only for the first `depth` levels. Hard links are counted once.
+pub fn du(path: &Path, depth: usize, job: &JobHandle) -> Result<DiskUsage> {
+    let mut seen = HashSet::new();
+    let mut scanned = 0;
+
+    disk_usage(path, depth, &mut seen, &mut scanned, job)
+}
+
+fn disk_usage(
+    path: &Path,
+    depth: usize,
+    seen: &mut HashSet<(u64, u64)>,
+    scanned: &mut u64,
+    job: &JobHandle,
+) -> Result<DiskUsage> {
+    job.check()?;
+
+    let meta = path.symlink_metadata()?;
+    let mut res = DiskUsage {
+        path: path.to_path_buf(),
+        size: 0,
+        allocated_size: 0,
+        files: 0,
+        dirs: 0,
+        children: vec![],
+    };
+
+    if !meta.is_dir() {
+        if file_id(&meta).is_none_or(|id| seen.insert(id)) {
+            res.size = meta.len();
+            res.allocated_size = allocated_size(&meta);
+            res.files = 1;
+        }
+
+        return Ok(res);
+    }
+
+    res.dirs = 1;
+    res.allocated_size = allocated_size(&meta);
+
+    for entry in fs::read_dir(path)?.flatten() {
+        // unreadable entries are left out of the totals
+        let Ok(child) = disk_usage(&entry.path(), depth.saturating_sub(1), seen, scanned, job)
+        else {
+            job.check()?;
+            continue;
+        };
+
+        res.size += child.size;
+        res.allocated_size += child.allocated_size;
+        res.files += child.files;
+        res.dirs += child.dirs;
+
+        if depth > 0 {
+            res.children.push(child);
+        }
+    }
+
+    res.children.sort_by_key(|it| std::cmp::Reverse(it.size));
+
+    *scanned += 1;
+    job.progress(*scanned, None, Some(path.to_string_lossy().to_string()));
+
+    Ok(res)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mount {
+    pub source: String,
+    pub path: PathBuf,
+    pub fs_type: String,
+}
+
+/// Lists the mounted filesystems, empty where `/proc` is not available.
+pub fn mounts() -> Vec<Mount> {
+    let Ok(contents) = fs::read_to_string("/proc/self/mounts") else {
+        return vec![];
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+
+            Some(Mount {
+                source: unescape_mount(fields.next()?),
+                path: PathBuf::from(unescape_mount(fields.next()?)),
+                fs_type: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+// mount points escape whitespace and backslashes as octal sequences
+fn unescape_mount(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.by_ref().take(3).collect();
+
+            if let Ok(byte) = u8::from_str_radix(&code, 8) {
+                res.push(byte as char);
+                continue;
+            }
+
+            res.push(c);
+            res.push_str(&code);
+        } else {
+            res.push(c);
+        }
+    }
+
+    res
+}
+
+/// Walks up from `path` until the device changes.
+pub fn mount_point(path: &Path) -> Result<PathBuf> {
+    let dev = device(path)?;
+    let mut res = path.to_path_buf();
+
+    for ancestor in path.ancestors().skip(1) {
+        if device(ancestor)? != dev {
+            break;
+        }
+
+        res = ancestor.to_path_buf();
+    }
+
+    Ok(res)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskInfo {
+    pub mount_point: PathBuf,
+    pub source: Option<String>,
+    pub fs_type: Option<String>,
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    pub available_bytes: u64,
+}
+
+pub fn disk_info(path: &Path) -> Result<DiskInfo> {
+    let path = std::path::absolute(path)?;
+    let mount_point = mount_point(&path)?;
+    let (total_bytes, free_bytes, available_bytes) = statvfs(&path)?;
+    let mount = mounts().into_iter().rfind(|it| it.path == mount_point);
+
+    Ok(DiskInfo {
+        mount_point,
+        source: mount.as_ref().map(|it| it.source.clone()),
+        fs_type: mount.map(|it| it.fs_type),
+        total_bytes,
+        free_bytes,
+        available_bytes,
+    })
+}
+
+// the field widths differ between platforms
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &Path) -> Result<(u64, u64, u64)> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let stat = unsafe { stat.assume_init() };
+    let block = stat.f_frsize as u64;
+
+    Ok((
+        stat.f_blocks as u64 * block,
+        stat.f_bfree as u64 * block,
+        stat.f_bavail as u64 * block,
+    ))
+}
+
+#[cfg(not(unix))]
+fn statvfs(_: &Path) -> Result<(u64, u64, u64)> {
+    Err(Error::new("disk info is not supported on this platform"))
+}
+
+#[cfg(unix)]
+pub fn device(path: &Path) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(path.symlink_metadata()?.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device(_: &Path) -> Result<u64> {
+    Err(Error::new("devices are not supported on this platform"))
+}
+
+#[cfg(unix)]
+pub fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn allocated_size(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(meta: &Metadata) -> u64 {
+    meta.len()
+}
+
+pub fn read_archive(
+    cache: &Cache,
+    path: &str,
+    container: Option<&str>,
+    options: &ListOptions,
+) -> Result<Vec<archive::Entry>> {
+    archive::list(cache, path, container, options)
 }
@@ -0,0 +1,196 @@
+use crate::error::Result;
+use crate::fs::file_id;
+use crate::jobs::JobHandle;
+use blake3::Hasher as Blake3;
+use crc32fast::Hasher as Crc32;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+    Crc32,
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(Md5),
+    Blake3(Box<Blake3>),
+    Crc32(Crc32),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(Sha256::new()),
+            Algorithm::Sha1 => Self::Sha1(Sha1::new()),
+            Algorithm::Md5 => Self::Md5(Md5::new()),
+            Algorithm::Blake3 => Self::Blake3(Box::new(Blake3::new())),
+            Algorithm::Crc32 => Self::Crc32(Crc32::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+            Self::Crc32(h) => h.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Self::Sha256(h) => hex::encode(h.finalize()),
+            Self::Sha1(h) => hex::encode(h.finalize()),
+            Self::Md5(h) => hex::encode(h.finalize()),
+            Self::Blake3(h) => h.finalize().to_hex().to_string(),
+            Self::Crc32(h) => format!("{:08x}", h.finalize()),
+        }
+    }
+}
+
+/// Streams the file through the hasher, calling `on_read` with the size of every chunk.
+/// This does blocking reads so it should be run off the async runtime.
+pub fn hash_file(
+    path: &Path,
+    algorithm: Algorithm,
+    mut on_read: impl FnMut(usize) -> Result<()>,
+) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..read]);
+        on_read(read)?;
+    }
+
+    Ok(hasher.finalize())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Duplicates {
+    pub groups: Vec<DuplicateGroup>,
+    pub reclaimable_bytes: u64,
+}
+
+/// Groups files under `root` by size first and only hashes the sizes that collide.
+/// Hard links to the same inode are counted once since removing them frees nothing.
+pub fn find_duplicates(
+    root: &Path,
+    algorithm: Algorithm,
+    min_size: u64,
+    job: &JobHandle,
+) -> Result<Duplicates> {
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+
+    collect_files(root, min_size, &mut by_size, &mut seen, job)?;
+    by_size.retain(|_, paths| paths.len() > 1);
+
+    let total = by_size
+        .iter()
+        .map(|(size, paths)| size * paths.len() as u64)
+        .sum();
+    let mut done = 0;
+    let mut groups = vec![];
+
+    for (size, paths) in by_size {
+        let mut by_hash: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+
+        for path in paths {
+            let message = Some(path.to_string_lossy().to_string());
+
+            // unreadable files are skipped rather than failing the whole scan
+            if let Ok(hash) = hash_file(&path, algorithm, |read| {
+                done += read as u64;
+                job.progress(done, Some(total), message.clone());
+                job.check()
+            }) {
+                by_hash.entry(hash).or_default().push(path);
+            }
+
+            job.check()?;
+        }
+
+        groups.extend(
+            by_hash
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|(hash, paths)| DuplicateGroup { hash, size, paths }),
+        );
+    }
+
+    groups.sort_by_key(|it| std::cmp::Reverse(it.size * (it.paths.len() as u64 - 1)));
+
+    let reclaimable_bytes = groups
+        .iter()
+        .map(|it| it.size * (it.paths.len() as u64 - 1))
+        .sum();
+
+    Ok(Duplicates {
+        groups,
+        reclaimable_bytes,
+    })
+}
+
+fn collect_files(
+    dir: &Path,
+    min_size: u64,
+    by_size: &mut BTreeMap<u64, Vec<PathBuf>>,
+    seen: &mut HashSet<(u64, u64)>,
+    job: &JobHandle,
+) -> Result<()> {
+    job.check()?;
+
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let Ok(meta) = path.symlink_metadata() else {
+            continue;
+        };
+
+        if meta.is_dir() {
+            let _ = collect_files(&path, min_size, by_size, seen, job);
+            job.check()?;
+        } else if meta.is_file() && meta.len() >= min_size {
+            if let Some(id) = file_id(&meta)
+                && !seen.insert(id)
+            {
+                continue;
+            }
+
+            by_size.entry(meta.len()).or_default().push(path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+// https://www.rfc-editor.org/rfc/rfc9110#name-range-requests
+// https://www.rfc-editor.org/rfc/rfc9110#name-conditional-requests
+
+use axum::http::HeaderMap;
+use axum::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE};
+use chrono::{DateTime, Utc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// More ranges than this are served as a whole instead.
+const MAX_RANGES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    /// Inclusive, like in the headers.
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        match self.is_empty() {
+            true => 0,
+            false => self.end - self.start + 1,
+        }
+    }
+
+    /// Parsed ranges always hold at least one byte, only one built by hand
+    /// with the end before the start is empty.
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{size}", self.start, self.end)
+    }
+}
+
+#[derive(Debug)]
+pub enum Ranges {
+    Full,
+    Partial(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header against the size of the representation. A header
+/// that can't be parsed is ignored, as the spec allows.
+pub fn parse_ranges(value: &str, size: u64) -> Ranges {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return Ranges::Full;
+    };
+
+    let mut res = vec![];
+
+    for spec in specs.split(',') {
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return Ranges::Full;
+        };
+
+        let range = match (start.trim(), end.trim()) {
+            ("", "") => return Ranges::Full,
+            ("", suffix) => {
+                let Ok(suffix) = suffix.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+
+                if suffix == 0 || size == 0 {
+                    continue;
+                }
+
+                ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                }
+            }
+            (start, end) => {
+                let Ok(start) = start.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+
+                let end = match end {
+                    "" => u64::MAX,
+                    end => match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return Ranges::Full,
+                    },
+                };
+
+                if start >= size {
+                    continue;
+                }
+
+                ByteRange {
+                    start,
+                    end: end.min(size - 1),
+                }
+            }
+        };
+
+        res.push(range);
+    }
+
+    match res.len() {
+        0 => Ranges::Unsatisfiable,
+        n if n > MAX_RANGES => Ranges::Full,
+        _ => Ranges::Partial(res),
+    }
+}
+
+pub fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(SystemTime::from)
+}
+
+/// Whether a `GET` can be answered with 304. `If-Modified-Since` only counts
+/// when there's no `If-None-Match`.
+pub fn is_not_modified(headers: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    if let Some(value) = header_str(headers, IF_NONE_MATCH) {
+        return value.trim() == "*" || value.split(',').any(|it| weak_eq(it, etag));
+    }
+
+    header_str(headers, IF_MODIFIED_SINCE)
+        .and_then(parse_http_date)
+        .is_some_and(|since| secs(modified) <= secs(since))
+}
+
+/// `If-Range` only lets the range through when the representation is unchanged.
+pub fn is_range_fresh(headers: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    match header_str(headers, IF_RANGE).map(str::trim) {
+        None => true,
+        // weak tags never match here
+        Some(value) if value.starts_with('"') => value == etag,
+        Some(value) if value.starts_with("W/") => false,
+        Some(value) => parse_http_date(value).is_some_and(|it| secs(it) == secs(modified)),
+    }
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    let strip = |it: &str| it.trim().trim_start_matches("W/").to_string();
+    strip(a) == strip(b)
+}
+
+fn header_str(headers: &HeaderMap, name: axum::http::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|it| it.to_str().ok())
+}
+
+// http dates only have second precision
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,327 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::task::spawn_blocking;
+use tokio::time::{Instant, timeout_at};
+use tokio_util::sync::CancellationToken;
+
+/// Progress is sent at most this often, the final value always goes out.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Progress {
+    pub done: u64,
+    pub total: Option<u64>,
+    pub message: Option<String>,
+    /// Bytes per second, only set by jobs that move data over time.
+    pub speed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: u64,
+    pub kind: String,
+    pub status: Status,
+    pub progress: Progress,
+    pub result: Option<JsonValue>,
+    pub error: Option<String>,
+    pub started_time: u64,
+    pub finished_time: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    job: Job,
+    token: CancellationToken,
+}
+
+/// Updates that don't fit are dropped for slow listeners, they catch up with the next one.
+const EVENTS_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct Jobs {
+    entries: Arc<RwLock<BTreeMap<u64, Entry>>>,
+    next_id: Arc<AtomicU64>,
+    events: broadcast::Sender<Job>,
+    /// How long finished jobs are kept around for `status` and `list`.
+    retention: Duration,
+}
+
+impl Jobs {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            entries: Arc::default(),
+            next_id: Arc::default(),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            retention,
+        }
+    }
+
+    pub fn spawn<F, Fut>(&self, kind: &str, f: F) -> Result<Job>
+    where
+        F: FnOnce(JobHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<JsonValue>> + Send + 'static,
+    {
+        let (job, handle) = self.register(kind)?;
+
+        tokio::spawn(async move {
+            let res = f(handle.clone()).await;
+            handle.finish(res);
+        });
+
+        Ok(job)
+    }
+
+    pub fn spawn_blocking<F>(&self, kind: &str, f: F) -> Result<Job>
+    where
+        F: FnOnce(&JobHandle) -> Result<JsonValue> + Send + 'static,
+    {
+        let (job, handle) = self.register(kind)?;
+
+        spawn_blocking(move || {
+            let res = f(&handle);
+            handle.finish(res);
+        });
+
+        Ok(job)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Job> {
+        self.entries
+            .read()?
+            .get(&id)
+            .map(|it| it.job.clone())
+            .ok_or_else(|| Error::new(format!("job not found: {id}")))
+    }
+
+    pub fn list(&self) -> Result<Vec<Job>> {
+        self.prune()?;
+
+        Ok(self
+            .entries
+            .read()?
+            .values()
+            .map(|it| it.job.clone())
+            .collect())
+    }
+
+    pub fn cancel(&self, id: u64) -> Result<Job> {
+        let entries = self.entries.read()?;
+        let entry = entries
+            .get(&id)
+            .ok_or_else(|| Error::new(format!("job not found: {id}")))?;
+
+        entry.token.cancel();
+        Ok(entry.job.clone())
+    }
+
+    /// Every change to every job, starting from when this is called.
+    pub fn subscribe(&self) -> broadcast::Receiver<Job> {
+        self.events.subscribe()
+    }
+
+    /// Resolves once the job has finished, or with the job as it is when `timeout` runs out.
+    pub async fn wait(&self, id: u64, timeout: Option<Duration>) -> Result<Job> {
+        let deadline = timeout.map(|it| Instant::now() + it);
+        let mut events = self.subscribe();
+
+        loop {
+            let job = self.get(id)?;
+
+            if job.status != Status::Running {
+                return Ok(job);
+            }
+
+            // anything missed is picked up by `get` on the next round
+            let event = match deadline {
+                Some(deadline) => match timeout_at(deadline, events.recv()).await {
+                    Ok(event) => event,
+                    Err(_) => return Ok(job),
+                },
+                None => events.recv().await,
+            };
+
+            if let Err(broadcast::error::RecvError::Closed) = event {
+                return Ok(job);
+            }
+        }
+    }
+
+    fn prune(&self) -> Result<()> {
+        let expired = now().saturating_sub(self.retention.as_millis() as u64);
+
+        self.entries
+            .write()?
+            .retain(|_, it| it.job.finished_time.is_none_or(|time| time > expired));
+
+        Ok(())
+    }
+
+    fn register(&self, kind: &str) -> Result<(Job, JobHandle)> {
+        self.prune()?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = CancellationToken::new();
+
+        let job = Job {
+            id,
+            kind: kind.to_string(),
+            status: Status::Running,
+            progress: Progress::default(),
+            result: None,
+            error: None,
+            started_time: now(),
+            finished_time: None,
+        };
+
+        self.entries.write()?.insert(
+            id,
+            Entry {
+                job: job.clone(),
+                token: token.clone(),
+            },
+        );
+
+        let handle = JobHandle {
+            id,
+            token,
+            jobs: self.clone(),
+            throttle: Arc::default(),
+        };
+
+        self.events.send(job.clone()).ok();
+
+        Ok((job, handle))
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        if let Ok(mut entries) = self.entries.write()
+            && let Some(entry) = entries.get_mut(&id)
+        {
+            f(&mut entry.job);
+            self.events.send(entry.job.clone()).ok();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    pub id: u64,
+    pub token: CancellationToken,
+    jobs: Jobs,
+    throttle: Arc<Mutex<Throttle>>,
+}
+
+#[derive(Debug, Default)]
+struct Throttle {
+    sent: Option<Instant>,
+    /// Held back for being too soon after the last one, applied when the job finishes.
+    pending: Option<Progress>,
+}
+
+impl JobHandle {
+    /// Cheap to call for every chunk, updates are throttled to [`PROGRESS_INTERVAL`]
+    /// except for the one reaching the total.
+    pub fn progress(&self, done: u64, total: Option<u64>, message: Option<String>) {
+        self.report(Progress {
+            done,
+            total,
+            message,
+            speed: None,
+        });
+    }
+
+    pub fn transfer(&self, done: u64, total: Option<u64>, speed: u64) {
+        self.report(Progress {
+            done,
+            total,
+            message: None,
+            speed: Some(speed),
+        });
+    }
+
+    fn report(&self, progress: Progress) {
+        let last = progress.total.is_some_and(|it| progress.done >= it);
+
+        if let Ok(mut throttle) = self.throttle.lock() {
+            if !last
+                && throttle
+                    .sent
+                    .is_some_and(|it| it.elapsed() < PROGRESS_INTERVAL)
+            {
+                throttle.pending = Some(progress);
+                return;
+            }
+
+            throttle.sent = Some(Instant::now());
+            throttle.pending = None;
+        }
+
+        self.jobs.update(self.id, |job| job.progress = progress);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Bails out of the work with an error once the job has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::new("cancelled"));
+        }
+
+        Ok(())
+    }
+
+    fn finish(&self, res: Result<JsonValue>) {
+        let cancelled = self.is_cancelled();
+        let pending = self
+            .throttle
+            .lock()
+            .ok()
+            .and_then(|mut it| it.pending.take());
+
+        self.jobs.update(self.id, |job| {
+            job.finished_time = Some(now());
+
+            if let Some(progress) = pending {
+                job.progress = progress;
+            }
+
+            match res {
+                _ if cancelled => job.status = Status::Cancelled,
+                Ok(value) => {
+                    job.status = Status::Completed;
+                    job.result = Some(value);
+                }
+                Err(err) => {
+                    job.status = Status::Failed;
+                    job.error = Some(err.message);
+                }
+            }
+        });
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis() as u64)
+        .unwrap_or_default()
+}
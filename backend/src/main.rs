@@ -1,13 +1,32 @@
+pub mod archive;
 pub mod config;
 pub mod db;
+pub mod download;
 pub mod error;
 pub mod fs;
+pub mod hash;
+pub mod http;
+pub mod jobs;
+pub mod mime;
+pub mod presets;
+pub mod process;
 pub mod routes;
+pub mod schedule;
+pub mod system;
+pub mod terminal;
+pub mod thumbnail;
+pub mod trash;
 pub mod utils;
 
+use crate::archive::cache::Cache as ArchiveCache;
 use crate::config::Config;
+use crate::download::queue::Queue as DownloadQueue;
 use crate::error::{Error, Result};
+use crate::jobs::Jobs;
+use crate::process::Processes;
 use crate::routes::bridges;
+use crate::schedule::Scheduler;
+use crate::terminal::Terminals;
 use crate::utils::State;
 use axum::Router;
 use axum::extract::DefaultBodyLimit;
@@ -16,6 +35,7 @@ use std::collections::BTreeMap;
 use std::env;
 use std::sync::{Arc, RwLock};
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tower_http::cors::CorsLayer;
 use tower_http::services::{ServeDir, ServeFile};
 
@@ -28,13 +48,52 @@ async fn main() -> Result<()> {
     let user_agent = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
     let http_client = HttpClient::builder().user_agent(user_agent).build()?;
 
+    let archives = ArchiveCache::new(
+        config.cache.archives(),
+        config.cache.archive_bytes(),
+        config.cache.spool_bytes(),
+    );
+    let jobs = Jobs::new(config.jobs.retention());
+
+    let downloads = DownloadQueue::new(
+        config.downloads.state(&config.meta.path),
+        config.downloads.clone(),
+        http_client.clone(),
+        jobs.clone(),
+    )?;
+
+    downloads.start();
+
+    let schedule_path = config.schedule.state(&config.meta.path);
+    let schedule_options = config.schedule.clone();
+
+    let processes = Processes::default();
+    let terminals = Terminals::default();
     let config = Arc::new(RwLock::new(config));
+
+    let scheduler = Scheduler::new(
+        schedule_path,
+        schedule_options,
+        config.clone(),
+        http_client.clone(),
+        jobs.clone(),
+    )?;
+
+    scheduler.start();
     let store = Arc::new(RwLock::new(BTreeMap::new()));
+    let shutdown = CancellationToken::new();
 
     let state = State {
         http_client,
         config,
         store,
+        jobs,
+        archives,
+        downloads,
+        processes: processes.clone(),
+        scheduler,
+        terminals: terminals.clone(),
+        shutdown: shutdown.clone(),
         mongo_client: None,
         sqlite_client: None,
     };
@@ -59,14 +118,20 @@ async fn main() -> Result<()> {
             .layer(DefaultBodyLimit::disable())
             .with_state(state),
     )
-    .with_graceful_shutdown(shutdown_signal())
+    .with_graceful_shutdown(shutdown_signal(shutdown, processes, terminals))
     .await?;
 
     Ok(())
 }
 
-async fn shutdown_signal() {
+async fn shutdown_signal(shutdown: CancellationToken, processes: Processes, terminals: Terminals) {
     tokio::signal::ctrl_c()
         .await
         .expect("failed to listen for ctrl-c");
+
+    // event streams that never end on their own would hold the graceful shutdown up
+    shutdown.cancel();
+
+    // children would otherwise outlive the backend, and their streams keep connections open
+    tokio::join!(processes.shutdown(), terminals.shutdown());
 }
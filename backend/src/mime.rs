@@ -0,0 +1,117 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Enough of the head of a file to cover every signature `infer` knows about.
+pub const SNIFF_LEN: usize = 8192;
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Image,
+    Video,
+    Audio,
+    Text,
+    Archive,
+    Document,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileType {
+    pub mime: String,
+    pub category: Category,
+}
+
+pub fn detect(path: &Path) -> Result<FileType> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+
+    Ok(detect_bytes(&head, &path.to_string_lossy()))
+}
+
+/// Sniffs the magic bytes first and falls back to the extension of `name`.
+/// Generic zip signatures defer to the extension since epub, cbz and the like are zips too.
+pub fn detect_bytes(head: &[u8], name: &str) -> FileType {
+    let guessed = mime_guess::from_path(name).first_raw();
+    let sniffed = infer::get(head).map(|it| it.mime_type());
+
+    let mime = match (sniffed, guessed) {
+        (Some("application/zip"), Some(guessed)) => guessed,
+        (Some(sniffed), _) => sniffed,
+        (None, Some(guessed)) => guessed,
+        (None, None) if is_text(head) => "text/plain",
+        (None, None) => OCTET_STREAM,
+    };
+
+    FileType {
+        mime: mime.to_string(),
+        category: category(mime),
+    }
+}
+
+/// Guesses from the extension alone, for entries that are too costly to open.
+pub fn detect_name(name: &str) -> FileType {
+    let mime = mime_guess::from_path(name)
+        .first_raw()
+        .unwrap_or(OCTET_STREAM);
+
+    FileType {
+        mime: mime.to_string(),
+        category: category(mime),
+    }
+}
+
+pub fn category(mime: &str) -> Category {
+    let (kind, subtype) = mime.split_once('/').unwrap_or((mime, ""));
+
+    match (kind, subtype) {
+        ("image", _) => Category::Image,
+        ("video", _) => Category::Video,
+        ("audio", _) => Category::Audio,
+        ("text", _) => Category::Text,
+        (
+            "application",
+            "json" | "xml" | "javascript" | "toml" | "yaml" | "x-yaml" | "x-sh" | "sql",
+        ) => Category::Text,
+        (
+            "application",
+            "zip" | "x-tar" | "gzip" | "x-gzip" | "x-bzip2" | "x-xz" | "zstd" | "x-7z-compressed"
+            | "vnd.rar" | "x-rar-compressed" | "x-cbr" | "x-cbz" | "x-cb7" | "x-lzip"
+            | "java-archive",
+        ) => Category::Archive,
+        ("application", "pdf" | "msword" | "rtf" | "epub+zip" | "vnd.ms-excel")
+        | ("application", "vnd.ms-powerpoint") => Category::Document,
+        ("application", it)
+            if it.starts_with("vnd.openxmlformats-officedocument")
+                || it.starts_with("vnd.oasis.opendocument") =>
+        {
+            Category::Document
+        }
+        _ => Category::Other,
+    }
+}
+
+// text has no signature so treat valid utf-8 without control bytes as text,
+// allowing a multi-byte sequence to be cut off at the end of the head
+fn is_text(head: &[u8]) -> bool {
+    if head.is_empty() {
+        return false;
+    }
+
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => head,
+        Err(err) if err.error_len().is_none() => &head[..err.valid_up_to()],
+        Err(_) => return false,
+    };
+
+    !valid
+        .iter()
+        .any(|b| b.is_ascii_control() && !b.is_ascii_whitespace() && *b != 0x1b)
+}
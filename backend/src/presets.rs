@@ -0,0 +1,225 @@
+use crate::config::{Param, ParamKind, Preset};
+use crate::error::{Error, Result};
+use crate::process::{Output, OutputOptions, output};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RunOptions {
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub base64: bool,
+}
+
+/// Runs a preset with the values given for its parameters. Each value stays a
+/// single argument, it is never split or seen by a shell.
+pub async fn run(
+    preset: &Preset,
+    params: &Map<String, JsonValue>,
+    options: RunOptions,
+) -> Result<Output> {
+    let args = expand(preset, params)?;
+
+    let options = OutputOptions {
+        cwd: preset.cwd.clone(),
+        env: preset.env.clone(),
+        stdin: options.stdin,
+        timeout_ms: preset.timeout_ms,
+        max_output_bytes: preset.max_output_bytes,
+        base64: options.base64,
+    };
+
+    output(&preset.program, &args, &options).await
+}
+
+/// Checks the values against the preset's parameters and fills in its argument templates.
+pub fn expand(preset: &Preset, params: &Map<String, JsonValue>) -> Result<Vec<String>> {
+    if let Some(name) = params.keys().find(|it| !preset.params.contains_key(*it)) {
+        return Err(Error::new(format!("unknown parameter: {name}")));
+    }
+
+    let mut values = BTreeMap::new();
+
+    for (name, param) in &preset.params {
+        let value = params
+            .get(name)
+            .filter(|it| !it.is_null())
+            .or(param.default.as_ref());
+
+        let value = match value {
+            Some(value) => format_value(name, param, value)?,
+            None if param.optional => None,
+            None => return Err(Error::new(format!("missing parameter: {name}"))),
+        };
+
+        values.insert(name.as_str(), value);
+    }
+
+    let mut args = vec![];
+
+    for template in &preset.args {
+        if let Some(arg) = render(template, &values)? {
+            args.push(arg);
+        }
+    }
+
+    Ok(args)
+}
+
+/// The text a value expands to, `None` leaves out the arguments using it.
+fn format_value(name: &str, param: &Param, value: &JsonValue) -> Result<Option<String>> {
+    if !param.choices.is_empty() && !param.choices.contains(value) {
+        return Err(Error::new(format!(
+            "parameter is not one of its choices: {name}"
+        )));
+    }
+
+    let res = match param.kind {
+        // listed choices are trusted, anything else could pass for an option
+        ParamKind::String => value
+            .as_str()
+            .filter(|it| param.allow_dash || !param.choices.is_empty() || !it.starts_with('-'))
+            .map(|it| Some(it.to_string())),
+        ParamKind::Integer => value
+            .as_i64()
+            .map(|it| it.to_string())
+            .or_else(|| value.as_u64().map(|it| it.to_string()))
+            .map(Some),
+        ParamKind::Number => value.is_number().then(|| Some(value.to_string())),
+        ParamKind::Boolean => value.as_bool().map(|it| it.then(String::new)),
+        // relative ones could pass for options, or depend on the cwd
+        ParamKind::Path => value
+            .as_str()
+            .filter(|it| Path::new(it).is_absolute())
+            .map(|it| Some(it.to_string())),
+    };
+
+    res.ok_or_else(|| Error::new(format!("invalid value for parameter: {name}")))
+}
+
+/// Replaces `{name}` in a template, `{{` and `}}` are literal braces.
+fn render(template: &str, values: &BTreeMap<&str, Option<String>>) -> Result<Option<String>> {
+    let mut arg = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        arg.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            arg.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+
+        let Some(end) = tail.find('}').filter(|_| tail.starts_with('{')) else {
+            return Err(Error::new(format!(
+                "unmatched brace in preset argument: {template}"
+            )));
+        };
+
+        match values.get(&tail[1..end]) {
+            Some(Some(value)) => arg.push_str(value),
+            Some(None) => return Ok(None),
+            None => {
+                return Err(Error::new(format!(
+                    "preset argument uses an undeclared parameter: {template}"
+                )));
+            }
+        }
+
+        rest = &tail[end + 1..];
+    }
+
+    arg.push_str(rest);
+    Ok(Some(arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{from_value, json};
+
+    fn preset(value: JsonValue) -> Preset {
+        from_value(value).unwrap()
+    }
+
+    fn params(value: JsonValue) -> Map<String, JsonValue> {
+        from_value(value).unwrap()
+    }
+
+    #[test]
+    fn render_escapes_braces() {
+        let values = BTreeMap::from([("name", Some("x".to_string()))]);
+
+        assert_eq!(
+            render("{{{name}}}", &values).unwrap().as_deref(),
+            Some("{x}")
+        );
+        assert_eq!(render("a}}b{{", &values).unwrap().as_deref(), Some("a}b{"));
+    }
+
+    #[test]
+    fn render_rejects_unmatched_braces_and_undeclared_names() {
+        let values = BTreeMap::from([("name", Some("x".to_string()))]);
+
+        assert!(render("{name", &values).is_err());
+        assert!(render("name}", &values).is_err());
+        assert!(render("{other}", &values).is_err());
+    }
+
+    #[test]
+    fn expand_drops_missing_optionals_and_false_booleans() {
+        let preset = preset(json!({
+            "program": "ls",
+            "args": ["--all={all}", "{verbose}-v", "--color={color}", "{path}"],
+            "params": {
+                "all": { "type": "boolean" },
+                "verbose": { "type": "boolean" },
+                "color": { "type": "string", "optional": true },
+                "path": { "type": "path" },
+            },
+        }));
+
+        let args = expand(
+            &preset,
+            &params(json!({ "all": false, "verbose": true, "path": "/tmp" })),
+        )
+        .unwrap();
+
+        assert_eq!(args, ["-v", "/tmp"]);
+    }
+
+    #[test]
+    fn expand_rejects_unknown_and_missing_params() {
+        let preset = preset(json!({
+            "program": "echo",
+            "args": ["{text}"],
+            "params": { "text": { "type": "string" } },
+        }));
+
+        assert!(expand(&preset, &params(json!({}))).is_err());
+        assert!(expand(&preset, &params(json!({ "text": "a", "other": 1 }))).is_err());
+    }
+
+    #[test]
+    fn expand_rejects_leading_dash_unless_allowed() {
+        let strict = preset(json!({
+            "program": "echo",
+            "args": ["{text}"],
+            "params": { "text": { "type": "string" } },
+        }));
+        let allowed = preset(json!({
+            "program": "echo",
+            "args": ["{text}"],
+            "params": { "text": { "type": "string", "allowDash": true } },
+        }));
+        let value = params(json!({ "text": "-rf" }));
+
+        assert!(expand(&strict, &value).is_err());
+        assert_eq!(expand(&allowed, &value).unwrap(), ["-rf"]);
+    }
+}
@@ -0,0 +1,763 @@
+use crate::error::{Error, Result};
+use crate::jobs::now;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::{Mutex as AsyncMutex, Notify, broadcast, watch};
+use tokio::time::{sleep, timeout};
+
+/// Lines kept for listeners that connect after the output was written.
+const HISTORY_LEN: usize = 1000;
+
+/// How long output is waited on after the process has exited.
+const READER_GRACE: Duration = Duration::from_secs(1);
+
+/// How long children get to exit on shutdown before they're killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+/// Longer output lines are split, so a stream without newlines can't grow one without bound.
+const MAX_LINE_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Running,
+    Exited,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Info {
+    pub id: u64,
+    pub pid: Option<u32>,
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub status: Status,
+    pub code: Option<i32>,
+    /// The signal that ended the process, unix only.
+    pub signal: Option<i32>,
+    pub started_time: u64,
+    pub finished_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    Output {
+        seq: u64,
+        stream: Stream,
+        line: String,
+    },
+    Exit {
+        seq: u64,
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+impl Event {
+    pub fn seq(&self) -> u64 {
+        match self {
+            Self::Output { seq, .. } | Self::Exit { seq, .. } => *seq,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Env {
+    /// Starts from an empty environment instead of the backend's.
+    #[serde(default)]
+    pub clear: bool,
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    #[serde(default)]
+    pub unset: Vec<String>,
+}
+
+impl Env {
+    pub fn apply(&self, command: &mut Command) {
+        if self.clear {
+            command.env_clear();
+        }
+
+        for key in &self.unset {
+            command.env_remove(key);
+        }
+
+        command.envs(&self.set);
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnOptions {
+    pub cwd: Option<PathBuf>,
+    pub env: Option<Env>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputOptions {
+    pub cwd: Option<PathBuf>,
+    pub env: Option<Env>,
+    /// Written to stdin, which is closed right after.
+    pub stdin: Option<String>,
+    pub timeout_ms: Option<u64>,
+    /// Per stream, the rest is read but dropped.
+    pub max_output_bytes: Option<usize>,
+    /// Also returns both streams as base64, for output that isn't text.
+    #[serde(default)]
+    pub base64: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: Option<i32>,
+    pub signal: Option<i32>,
+    pub stdout_base64: Option<String>,
+    pub stderr_base64: Option<String>,
+    pub truncated: bool,
+    pub timed_out: bool,
+    /// Something the process left running still held its output open, what
+    /// was read until it stopped being waited on is returned.
+    pub incomplete: bool,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Signal {
+    #[default]
+    Term,
+    Kill,
+    Int,
+    Hup,
+    Quit,
+    Usr1,
+    Usr2,
+}
+
+#[cfg(unix)]
+impl Signal {
+    pub fn raw(self) -> libc::c_int {
+        match self {
+            Self::Term => libc::SIGTERM,
+            Self::Kill => libc::SIGKILL,
+            Self::Int => libc::SIGINT,
+            Self::Hup => libc::SIGHUP,
+            Self::Quit => libc::SIGQUIT,
+            Self::Usr1 => libc::SIGUSR1,
+            Self::Usr2 => libc::SIGUSR2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct History {
+    events: VecDeque<Event>,
+    seq: u64,
+}
+
+#[derive(Debug)]
+struct Managed {
+    info: watch::Sender<Info>,
+    stdin: AsyncMutex<Option<ChildStdin>>,
+    history: Mutex<History>,
+    events: broadcast::Sender<Event>,
+}
+
+impl Managed {
+    fn push(&self, f: impl FnOnce(u64) -> Event) {
+        let Ok(mut history) = self.history.lock() else {
+            return;
+        };
+
+        history.seq += 1;
+        let event = f(history.seq);
+
+        if history.events.len() >= HISTORY_LEN {
+            history.events.pop_front();
+        }
+
+        history.events.push_back(event.clone());
+        self.events.send(event).ok();
+    }
+}
+
+/// Children started with `spawn`, kept until removed so their output can be
+/// read after they've exited.
+#[derive(Debug, Clone, Default)]
+pub struct Processes {
+    entries: Arc<RwLock<BTreeMap<u64, Arc<Managed>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Processes {
+    pub fn spawn(&self, cmd: &str, args: &[String], options: &SpawnOptions) -> Result<Info> {
+        let mut command = Command::new(cmd);
+
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        configure(&mut command, options.cwd.as_ref(), options.env.as_ref());
+
+        let mut child = command.spawn()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let info = Info {
+            id,
+            pid: child.id(),
+            cmd: cmd.to_string(),
+            args: args.to_vec(),
+            status: Status::Running,
+            code: None,
+            signal: None,
+            started_time: now(),
+            finished_time: None,
+        };
+
+        let managed = Arc::new(Managed {
+            info: watch::Sender::new(info.clone()),
+            stdin: AsyncMutex::new(child.stdin.take()),
+            history: Mutex::new(History {
+                events: VecDeque::new(),
+                seq: 0,
+            }),
+            events: broadcast::channel(HISTORY_LEN).0,
+        });
+
+        self.entries.write()?.insert(id, managed.clone());
+
+        let readers = [
+            child
+                .stdout
+                .take()
+                .map(|it| tokio::spawn(read_lines(it, Stream::Stdout, managed.clone()))),
+            child
+                .stderr
+                .take()
+                .map(|it| tokio::spawn(read_lines(it, Stream::Stderr, managed.clone()))),
+        ];
+
+        tokio::spawn(async move {
+            let status = child.wait().await;
+
+            // the exit goes after the last line, unless something it left
+            // running in the background still holds the pipes
+            for reader in readers.into_iter().flatten() {
+                timeout(READER_GRACE, reader).await.ok();
+            }
+
+            let (code, signal) = match &status {
+                Ok(status) => (status.code(), exit_signal(status)),
+                Err(_) => (None, None),
+            };
+
+            managed.stdin.lock().await.take();
+            managed.push(|seq| Event::Exit { seq, code, signal });
+            managed.info.send_modify(|info| {
+                info.status = Status::Exited;
+                info.code = code;
+                info.signal = signal;
+                info.finished_time = Some(now());
+            });
+        });
+
+        Ok(info)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Info> {
+        Ok(self.managed(id)?.info.borrow().clone())
+    }
+
+    pub fn list(&self) -> Result<Vec<Info>> {
+        Ok(self
+            .entries
+            .read()?
+            .values()
+            .map(|it| it.info.borrow().clone())
+            .collect())
+    }
+
+    /// Writes to the child's stdin, `close` sends end of file afterwards.
+    pub async fn write_stdin(&self, id: u64, data: &[u8], close: bool) -> Result<()> {
+        let managed = self.managed(id)?;
+        let mut stdin = managed.stdin.lock().await;
+
+        let writer = stdin
+            .as_mut()
+            .ok_or_else(|| Error::new(format!("stdin is closed: {id}")))?;
+
+        writer.write_all(data).await?;
+        writer.flush().await?;
+
+        if close {
+            stdin.take();
+        }
+
+        Ok(())
+    }
+
+    pub fn kill(&self, id: u64, signal: Signal) -> Result<Info> {
+        let managed = self.managed(id)?;
+        let info = managed.info.borrow().clone();
+
+        if info.status == Status::Running
+            && let Some(pid) = info.pid
+        {
+            send_signal(pid, signal)?;
+        }
+
+        Ok(info)
+    }
+
+    /// Resolves once the process has exited, or with it still running when `timeout` runs out.
+    pub async fn wait(&self, id: u64, wait: Option<Duration>) -> Result<Info> {
+        let managed = self.managed(id)?;
+        let mut info = managed.info.subscribe();
+
+        let exited = async {
+            info.wait_for(|it| it.status == Status::Exited)
+                .await
+                .map(|it| it.clone())
+                .map_err(|_| Error::new(format!("process is gone: {id}")))
+        };
+
+        match wait {
+            Some(wait) => match timeout(wait, exited).await {
+                Ok(res) => res,
+                Err(_) => Ok(managed.info.borrow().clone()),
+            },
+            None => exited.await,
+        }
+    }
+
+    /// Drops an exited process and its output.
+    pub fn remove(&self, id: u64) -> Result<Info> {
+        let info = self.get(id)?;
+
+        if info.status == Status::Running {
+            return Err(Error::new(format!("process is running: {id}")));
+        }
+
+        self.entries.write()?.remove(&id);
+        Ok(info)
+    }
+
+    /// Output after `since`, followed by everything written from now on.
+    pub fn subscribe(
+        &self,
+        id: u64,
+        since: u64,
+    ) -> Result<(Vec<Event>, broadcast::Receiver<Event>)> {
+        let managed = self.managed(id)?;
+        let history = managed.history.lock()?;
+
+        // taken under the lock so nothing falls between the two
+        let receiver = managed.events.subscribe();
+        let history = history
+            .events
+            .iter()
+            .filter(|it| it.seq() > since)
+            .cloned()
+            .collect();
+
+        Ok((history, receiver))
+    }
+
+    /// Asks every running child to stop and kills the ones that don't in time.
+    pub async fn shutdown(&self) {
+        let running: Vec<u64> = self
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|it| it.status == Status::Running)
+            .map(|it| it.id)
+            .collect();
+
+        stop_all(
+            &running,
+            Signal::Term,
+            |id, signal| self.kill(id, signal),
+            |id, grace| async move { Ok(self.wait(id, Some(grace)).await?.status) },
+        )
+        .await;
+    }
+
+    fn managed(&self, id: u64) -> Result<Arc<Managed>> {
+        self.entries
+            .read()?
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::new(format!("process not found: {id}")))
+    }
+}
+
+/// Runs the command to completion and collects its output. A timeout kills
+/// it along with anything it started and returns what was written until then.
+pub async fn output(cmd: &str, args: &[String], options: &OutputOptions) -> Result<Output> {
+    let mut command = Command::new(cmd);
+
+    command
+        .args(args)
+        .stdin(match options.stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    configure(&mut command, options.cwd.as_ref(), options.env.as_ref());
+
+    let mut child = command.spawn()?;
+    let limit = options.max_output_bytes.unwrap_or(usize::MAX);
+
+    if let (Some(mut stdin), Some(data)) = (child.stdin.take(), options.stdin.clone()) {
+        // a child that doesn't read it all just gets a broken pipe
+        tokio::spawn(async move { stdin.write_all(data.as_bytes()).await });
+    }
+
+    let (mut stdout, mut stderr) = (Captured::default(), Captured::default());
+    let (stdout_pipe, stderr_pipe) = (child.stdout.take(), child.stderr.take());
+    let exited = Notify::new();
+
+    let waiting = async {
+        let status = match options.timeout_ms.map(Duration::from_millis) {
+            Some(wait) => match timeout(wait, child.wait()).await {
+                Ok(status) => status.map(Some),
+                Err(_) => {
+                    if let Some(pid) = child.id() {
+                        send_signal(pid, Signal::Kill).ok();
+                    }
+
+                    child.kill().await.ok();
+                    Ok(None)
+                }
+            },
+            None => child.wait().await.map(Some),
+        };
+
+        exited.notify_one();
+        status
+    };
+
+    // the pipes are drained while the process runs, after it has exited
+    // they get a grace period in case something it started holds them open
+    let reading = async {
+        let read = async {
+            tokio::join!(
+                read_limited(stdout_pipe, limit, &mut stdout),
+                read_limited(stderr_pipe, limit, &mut stderr),
+            )
+        };
+
+        tokio::select! {
+            _ = read => true,
+            _ = async {
+                exited.notified().await;
+                sleep(READER_GRACE).await;
+            } => false,
+        }
+    };
+
+    let (status, drained) = tokio::join!(waiting, reading);
+    let status = status?;
+
+    let encode = |data: &[u8]| options.base64.then(|| BASE64.encode(data));
+
+    Ok(Output {
+        stdout_base64: encode(&stdout.data),
+        stderr_base64: encode(&stderr.data),
+        stdout: String::from_utf8_lossy(&stdout.data).to_string(),
+        stderr: String::from_utf8_lossy(&stderr.data).to_string(),
+        status: status.and_then(|it| it.code()),
+        signal: status.as_ref().and_then(exit_signal),
+        truncated: stdout.truncated || stderr.truncated,
+        timed_out: status.is_none(),
+        incomplete: !drained,
+    })
+}
+
+fn configure(command: &mut Command, cwd: Option<&PathBuf>, env: Option<&Env>) {
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    if let Some(env) = env {
+        env.apply(command);
+    }
+
+    // its own group so a kill reaches whatever it started too
+    #[cfg(unix)]
+    command.process_group(0);
+}
+
+/// Opens `path` with the default application, or with `using`, without
+/// keeping any tie to the backend. The launched program gets its own session
+/// and none of our handles, so it doesn't hold the port after a restart.
+pub fn open_detached(path: &OsStr, using: Option<&str>) -> Result<()> {
+    let commands = match using {
+        Some(app) => vec![open::with_command(path, app)],
+        None => open::commands(path),
+    };
+
+    let mut last_err = None;
+
+    for mut command in commands {
+        detach(&mut command);
+
+        match command.spawn() {
+            // reaped in the background so it doesn't linger as a zombie
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+                return Ok(());
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(match last_err {
+        Some(err) => err.into(),
+        None => Error::new(format!("no way to open: {}", path.to_string_lossy())),
+    })
+}
+
+/// Shows `path` selected in the file manager, or opens the folder it's in
+/// when the file manager can't select.
+pub fn reveal(path: &Path) -> Result<()> {
+    let path = path.canonicalize()?;
+
+    if reveal_selected(&path) {
+        return Ok(());
+    }
+
+    let dir = match path.is_dir() {
+        true => path.as_path(),
+        false => path.parent().unwrap_or(&path),
+    };
+
+    open_detached(dir.as_os_str(), None)
+}
+
+#[cfg(target_os = "macos")]
+fn reveal_selected(path: &Path) -> bool {
+    let mut command = std::process::Command::new("open");
+    command.arg("-R").arg(path);
+    detach(&mut command);
+
+    command.status().is_ok_and(|it| it.success())
+}
+
+#[cfg(windows)]
+fn reveal_selected(path: &Path) -> bool {
+    let mut arg = std::ffi::OsString::from("/select,");
+    arg.push(path);
+
+    let mut command = std::process::Command::new("explorer");
+    command.arg(arg);
+    detach(&mut command);
+
+    // explorer exits with 1 even when it worked
+    command.spawn().is_ok()
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn reveal_selected(path: &Path) -> bool {
+    // https://www.freedesktop.org/wiki/Specifications/file-manager-interface/
+    let uri = format!("file://{}", crate::trash::encode_path(path));
+
+    let mut command = std::process::Command::new("dbus-send");
+    command.args([
+        "--session",
+        "--print-reply",
+        "--dest=org.freedesktop.FileManager1",
+        "--type=method_call",
+        "/org/freedesktop/FileManager1",
+        "org.freedesktop.FileManager1.ShowItems",
+        &format!("array:string:{uri}"),
+        "string:",
+    ]);
+    detach(&mut command);
+
+    command.status().is_ok_and(|it| it.success())
+}
+
+#[cfg(unix)]
+fn detach(command: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // only async-signal-safe calls are allowed between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // closing them here would also close the pipe std uses to
+            // report a failed exec, marking them is enough
+            let max = match libc::sysconf(libc::_SC_OPEN_MAX) {
+                n if n > 0 => n.min(65536) as libc::c_int,
+                _ => 1024,
+            };
+
+            for fd in 3..max {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+
+                if flags != -1 && flags & libc::FD_CLOEXEC == 0 {
+                    libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
+                }
+            }
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn detach(command: &mut std::process::Command) {
+    use std::os::windows::process::CommandExt;
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+#[derive(Default)]
+struct Captured {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+async fn read_limited(reader: Option<impl AsyncRead + Unpin>, limit: usize, res: &mut Captured) {
+    let Some(mut reader) = reader else {
+        return;
+    };
+
+    let mut buf = vec![0u8; 64 * 1024];
+
+    while let Ok(read) = reader.read(&mut buf).await
+        && read > 0
+    {
+        let keep = read.min(limit - res.data.len());
+        res.data.extend_from_slice(&buf[..keep]);
+        res.truncated |= keep < read;
+    }
+}
+
+/// Sends `signal` to every id, then gives them [`SHUTDOWN_GRACE`] to exit, all
+/// waited on at once, before whatever is still running gets killed.
+pub async fn stop_all<T, W>(
+    ids: &[u64],
+    signal: Signal,
+    kill: impl Fn(u64, Signal) -> Result<T>,
+    wait: impl Fn(u64, Duration) -> W,
+) where
+    W: Future<Output = Result<Status>>,
+{
+    for id in ids {
+        kill(*id, signal).ok();
+    }
+
+    let statuses = join_all(ids.iter().map(|id| wait(*id, SHUTDOWN_GRACE))).await;
+
+    for (id, status) in ids.iter().zip(statuses) {
+        if status.is_ok_and(|it| it == Status::Running) {
+            kill(*id, Signal::Kill).ok();
+        }
+    }
+}
+
+async fn read_lines(reader: impl AsyncRead + Unpin, stream: Stream, managed: Arc<Managed>) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = vec![];
+
+    // a line at the cap is emitted as is, the rest follows as the next one
+    while let Ok(read) = (&mut reader)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut buf)
+        .await
+        && read > 0
+    {
+        let line = String::from_utf8_lossy(&buf)
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+
+        managed.push(|seq| Event::Output { seq, stream, line });
+        buf.clear();
+    }
+}
+
+/// Signals the process group that `pid` leads.
+#[cfg(unix)]
+pub fn send_signal(pid: u32, signal: Signal) -> Result<()> {
+    // negative targets the whole group, see `process_group` in spawn
+    if unsafe { libc::kill(-(pid as libc::pid_t), signal.raw()) } != 0 {
+        let err = std::io::Error::last_os_error();
+
+        // already gone, it just hasn't been reaped yet
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err.into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn send_signal(pid: u32, _: Signal) -> Result<()> {
+    let status = std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .status()?;
+
+    if !status.success() {
+        return Err(Error::new(format!("failed to kill process: {pid}")));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal(_: &std::process::ExitStatus) -> Option<i32> {
+    None
+}
@@ -1,63 +1,677 @@
+use crate::archive::cache::Cache as ArchiveCache;
+use crate::archive::{self, Kind};
 use crate::error::{Error, Result};
-use crate::fs::CONTAINER_SEP;
+use crate::fs::{CONTAINER_SEP, read_dir};
+use crate::http::{ByteRange, Ranges, http_date, is_not_modified, is_range_fresh, parse_ranges};
+use crate::mime::{self, SNIFF_LEN};
+use crate::thumbnail::{self, thumbnail};
+use crate::utils::{State, natural_cmp};
+use axum::Json;
 use axum::body::Body;
-use axum::extract::{Path, Query};
-use axum::response::IntoResponse;
+use axum::extract::{Path, Query, State as StateExtractor};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
 use bytes::Bytes;
+use chrono::{Local, TimeZone};
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use serde::Serialize;
 use serde_json::Value as JsonValue;
-use std::io::Read;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
 use tokio::task::spawn_blocking;
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_util::io::ReaderStream;
-use zip::ZipArchive;
+
+const CHUNK_LEN: usize = 64 * 1024;
+
+// slashes stay readable, the container separator has to be encoded
+const HREF_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Debug, Clone)]
+enum Target {
+    File(PathBuf),
+    Entry {
+        path: String,
+        name: String,
+        container: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Asset {
+    size: u64,
+    modified: SystemTime,
+    etag: String,
+    content_type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Listing {
+    path: String,
+    parent: Option<String>,
+    entries: Vec<ListingEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListingEntry {
+    name: String,
+    /// Asset path of the entry, archives end with the separator so they open as a listing.
+    path: String,
+    is_dir: bool,
+    is_archive: bool,
+    size: Option<u64>,
+    modified_time: Option<u64>,
+    mime: Option<String>,
+    /// Set for files whose stats couldn't be read, the fields above are empty then.
+    unreadable: bool,
+}
+
+enum Part {
+    Bytes(Bytes),
+    Range(ByteRange),
+}
 
 pub async fn handler(
+    state: StateExtractor<State>,
     Path(path): Path<String>,
     Query(query): Query<JsonValue>,
-) -> Result<impl IntoResponse> {
-    match query["container"].as_str() {
-        Some("zip" | "cbz") => {
-            let (container_path, file_name) = path
-                .split_once(CONTAINER_SEP)
-                .map(|(a, b)| (a.to_string(), b.to_string()))
-                .ok_or_else(|| Error::new(format!("invalid path: {}", path)))?;
+    Query(params): Query<thumbnail::Params>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let container = query["container"].as_str().map(|it| it.to_string());
+
+    // without an explicit container, a separator in a path that doesn't exist
+    // as a plain file points into an archive whose format gets sniffed. the
+    // last separator splits off the entry, the ones before it nest archives
+    let entry = match &container {
+        Some(_) => Some(
+            path.rsplit_once(CONTAINER_SEP)
+                .ok_or_else(|| Error::new(format!("invalid path: {}", path)))?,
+        ),
+        None if !tokio::fs::try_exists(&path).await? => path.rsplit_once(CONTAINER_SEP),
+        None => None,
+    };
+
+    let target = match entry {
+        Some((container_path, name)) => Target::Entry {
+            path: container_path.to_string(),
+            name: name.to_string(),
+            container,
+        },
+        None => Target::File(PathBuf::from(&path)),
+    };
+
+    let cache = state.archives.clone();
+
+    let listing = {
+        let (cache, target) = (cache.clone(), target.clone());
+        spawn_blocking(move || list(&cache, &target)).await??
+    };
+
+    if let Some(listing) = listing {
+        let accept = headers.get(header::ACCEPT).and_then(|it| it.to_str().ok());
+        let vary = [(header::VARY, "accept")];
+
+        // browsers navigating to the url get a page, fetch gets json
+        return match accept.is_some_and(|it| it.contains("text/html")) {
+            true => Ok((vary, Html(listing.to_html())).into_response()),
+            false => Ok((vary, Json(listing)).into_response()),
+        };
+    }
+
+    let asset = {
+        let (cache, target) = (cache.clone(), target.clone());
+        spawn_blocking(move || resolve(&cache, &target)).await??
+    };
+
+    // thumbnails are served like any other file once they're in the cache
+    let (target, asset) = match params.is_empty() {
+        true => (target, asset),
+        false => {
+            if !asset.content_type.starts_with("image/") {
+                return Err(Error::new(format!(
+                    "can't make a thumbnail of {}",
+                    asset.content_type
+                ))
+                .with_status(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+            }
+
+            if asset.size > thumbnail::MAX_SOURCE_LEN {
+                return Err(source_too_large());
+            }
+
+            let (cache_dir, max_bytes) = {
+                let config = state.config.read()?;
+                (
+                    config.cache.dir().join("thumbnails"),
+                    config.cache.thumbnail_bytes(),
+                )
+            };
+            let key = format!("{path}|{}", asset.etag);
 
-            let container = std::fs::File::open(&container_path)?;
-            let (tx, rx) = mpsc::channel::<Result<Bytes>>(10);
+            let cache = cache.clone();
 
             spawn_blocking(move || {
-                if let Err(err) = (|| {
-                    let mut archive = ZipArchive::new(container)?;
-                    let mut file = archive.by_name(&file_name)?;
-                    let mut buf = [0u8; 8192];
-
-                    loop {
-                        let read = file.read(&mut buf)?;
-                        if read == 0 {
-                            break;
-                        }
-
-                        let bytes = Bytes::copy_from_slice(&buf[..read]);
-                        if tx.blocking_send(Ok(bytes)).is_err() {
-                            break;
-                        }
-                    }
+                let path = thumbnail(&cache_dir, max_bytes, &key, &params, || {
+                    read_all(&cache, &target, thumbnail::MAX_SOURCE_LEN)
+                })?;
+                let target = Target::File(path);
+                let asset = resolve(&cache, &target)?;
 
-                    Ok(())
-                })() {
-                    let _ = tx.blocking_send(Err(err));
+                Ok::<_, Error>((target, asset))
+            })
+            .await??
+        }
+    };
+
+    let mut res_headers = HeaderMap::new();
+    res_headers.insert(header::ETAG, HeaderValue::from_str(&asset.etag)?);
+    res_headers.insert(
+        header::LAST_MODIFIED,
+        HeaderValue::from_str(&http_date(asset.modified))?,
+    );
+    res_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    if is_not_modified(&headers, &asset.etag, asset.modified) {
+        return Ok((StatusCode::NOT_MODIFIED, res_headers).into_response());
+    }
+
+    let ranges = match headers.get(header::RANGE).and_then(|it| it.to_str().ok()) {
+        Some(value) if is_range_fresh(&headers, &asset.etag, asset.modified) => {
+            parse_ranges(value, asset.size)
+        }
+        _ => Ranges::Full,
+    };
+
+    let (status, content_type, parts) = match ranges {
+        Ranges::Unsatisfiable => {
+            res_headers.insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", asset.size))?,
+            );
+
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, res_headers).into_response());
+        }
+        Ranges::Full => {
+            let parts = (asset.size > 0)
+                .then(|| {
+                    Part::Range(ByteRange {
+                        start: 0,
+                        end: asset.size - 1,
+                    })
+                })
+                .into_iter()
+                .collect();
+
+            (StatusCode::OK, asset.content_type, parts)
+        }
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            res_headers.insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&ranges[0].content_range(asset.size))?,
+            );
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                asset.content_type,
+                vec![Part::Range(ranges[0])],
+            )
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = boundary();
+            let mut parts = vec![];
+
+            for range in ranges {
+                let head = format!(
+                    "\r\n--{boundary}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                    asset.content_type,
+                    range.content_range(asset.size)
+                );
+
+                parts.push(Part::Bytes(Bytes::from(head)));
+                parts.push(Part::Range(range));
+            }
+
+            parts.push(Part::Bytes(Bytes::from(format!("\r\n--{boundary}--\r\n"))));
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                format!("multipart/byteranges; boundary={boundary}"),
+                parts,
+            )
+        }
+    };
+
+    let content_length: u64 = parts
+        .iter()
+        .map(|it| match it {
+            Part::Bytes(bytes) => bytes.len() as u64,
+            Part::Range(range) => range.len(),
+        })
+        .sum();
+
+    res_headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+    res_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+
+    let (tx, rx) = mpsc::channel::<Result<Bytes>>(10);
+
+    spawn_blocking(move || {
+        if let Err(err) = send_parts(&cache, &target, parts, &tx) {
+            let _ = tx.blocking_send(Err(err));
+        }
+    });
+
+    let body = Body::from_stream(ReceiverStream::new(rx));
+    Ok((status, res_headers, body).into_response())
+}
+
+/// Lists directories and archive roots like `book.cbz>`, anything else is left to [`resolve`].
+fn list(cache: &ArchiveCache, target: &Target) -> Result<Option<Listing>> {
+    let mut listing = match target {
+        Target::File(path) if path.is_dir() => {
+            let dir = path.to_string_lossy().trim_end_matches('/').to_string();
+            let mut entries = vec![];
+
+            for entry in read_dir(path, true)? {
+                let Some(stats) = entry.stats else {
+                    entries.push(ListingEntry {
+                        path: format!("{dir}/{}", entry.name),
+                        name: entry.name,
+                        is_dir: false,
+                        is_archive: false,
+                        size: None,
+                        modified_time: None,
+                        mime: None,
+                        unreadable: true,
+                    });
+                    continue;
+                };
+
+                let is_archive = !stats.is_dir && is_archive(&entry.name);
+                let sep = if is_archive { CONTAINER_SEP } else { "" };
+
+                entries.push(ListingEntry {
+                    path: format!("{dir}/{}{sep}", entry.name),
+                    name: entry.name,
+                    is_dir: stats.is_dir,
+                    is_archive,
+                    size: (!stats.is_dir).then_some(stats.size),
+                    modified_time: Some(stats.modified_time),
+                    mime: stats.mime,
+                    unreadable: false,
+                });
+            }
+
+            Listing {
+                parent: path.parent().map(|it| it.to_string_lossy().to_string()),
+                path: path.to_string_lossy().to_string(),
+                entries,
+            }
+        }
+        Target::Entry {
+            path,
+            name,
+            container,
+        } if name.is_empty() || name.ends_with('/') => {
+            let entries = cache.with(path, container.as_deref(), |archive| archive.entries())?;
+
+            Listing {
+                parent: Some(archive_parent(path, name)),
+                path: format!("{path}{CONTAINER_SEP}{name}"),
+                entries: archive_children(entries, path, name),
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    listing.entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| natural_cmp(&a.name, &b.name))
+    });
+
+    Ok(Some(listing))
+}
+
+/// Picks the immediate children under `prefix`, directories that only show up
+/// as part of longer names are listed too.
+fn archive_children(entries: Vec<archive::Entry>, path: &str, prefix: &str) -> Vec<ListingEntry> {
+    let mut res: BTreeMap<String, ListingEntry> = BTreeMap::new();
+
+    for entry in entries {
+        let Some(rest) = entry.name.strip_prefix(prefix).filter(|it| !it.is_empty()) else {
+            continue;
+        };
+
+        let (name, is_dir, exact) = match rest.split_once('/') {
+            Some((name, tail)) => (name, true, tail.is_empty()),
+            None => (rest, entry.is_dir, true),
+        };
+
+        if !exact && res.contains_key(name) {
+            continue;
+        }
+
+        let is_archive = !is_dir && is_archive(name);
+        let suffix = match (is_dir, is_archive) {
+            (true, _) => "/",
+            (false, true) => CONTAINER_SEP,
+            _ => "",
+        };
+
+        res.insert(
+            name.to_string(),
+            ListingEntry {
+                name: name.to_string(),
+                path: format!("{path}{CONTAINER_SEP}{prefix}{name}{suffix}"),
+                is_dir,
+                is_archive,
+                size: (exact && !is_dir).then_some(entry.size),
+                modified_time: entry.modified_time.filter(|_| exact),
+                mime: entry.mime.filter(|_| exact),
+                unreadable: false,
+            },
+        );
+    }
+
+    res.into_values().collect()
+}
+
+fn archive_parent(path: &str, prefix: &str) -> String {
+    // a directory inside the archive goes up a level, the root goes to where the archive is
+    if let Some((parent, _)) = prefix.trim_end_matches('/').rsplit_once('/') {
+        return format!("{path}{CONTAINER_SEP}{parent}/");
+    }
+
+    if !prefix.is_empty() {
+        return format!("{path}{CONTAINER_SEP}");
+    }
+
+    match path.rsplit_once(CONTAINER_SEP) {
+        Some((outer, name)) => match name.rsplit_once('/') {
+            Some((dir, _)) => format!("{outer}{CONTAINER_SEP}{dir}/"),
+            None => format!("{outer}{CONTAINER_SEP}"),
+        },
+        None => std::path::Path::new(path)
+            .parent()
+            .map(|it| it.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn is_archive(name: &str) -> bool {
+    Kind::from_file_name(name).is_some()
+}
+
+impl Listing {
+    fn to_html(&self) -> String {
+        let mut rows = String::new();
+
+        if let Some(parent) = &self.parent {
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}\">..</a></td><td></td><td></td></tr>\n",
+                href(parent)
+            ));
+        }
+
+        for entry in &self.entries {
+            let modified = entry
+                .modified_time
+                .and_then(|it| Local.timestamp_millis_opt(it as i64).single())
+                .map(|it| it.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}\">{}{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+                href(&entry.path),
+                escape_html(&entry.name),
+                if entry.is_dir { "/" } else { "" },
+                entry.size.map(|it| it.to_string()).unwrap_or_default(),
+                modified
+            ));
+        }
+
+        let title = escape_html(&format!("Index of {}", self.path));
+
+        format!(
+            "<!doctype html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; }}
+td {{ padding: 0.2rem 1.5rem 0.2rem 0; }}
+td.size {{ text-align: right; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<table>
+{rows}</table>
+</body>
+</html>
+"
+        )
+    }
+}
+
+fn href(path: &str) -> String {
+    format!(
+        "/api/bridges/asset/{}",
+        utf8_percent_encode(path, HREF_ENCODE_SET)
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn resolve(cache: &ArchiveCache, target: &Target) -> Result<Asset> {
+    match target {
+        Target::File(path) => {
+            let meta = fs::metadata(path)?;
+
+            if meta.is_dir() {
+                return Err(Error::new(format!("is a directory: {}", path.display())));
+            }
+
+            let modified = meta.modified()?;
+
+            Ok(Asset {
+                size: meta.len(),
+                modified,
+                etag: format!("\"{:x}-{:x}\"", meta.len(), nanos(modified)),
+                content_type: mime::detect(path)?.mime,
+            })
+        }
+        Target::Entry {
+            path,
+            name,
+            container,
+        } => {
+            // validators come from the outermost file, entry times in archives are too coarse
+            let meta = fs::metadata(archive::outer_path(path)?)?;
+            let modified = meta.modified()?;
+
+            let mut head = Vec::with_capacity(SNIFF_LEN);
+
+            let entry = cache.with(path, container.as_deref(), |archive| {
+                let entry = archive.entry(name)?;
+
+                if entry.is_dir {
+                    return Err(Error::new(format!("is a directory: {name}")));
                 }
-            });
 
-            let stream = ReceiverStream::new(rx);
-            Ok(Body::from_stream(stream))
+                // the content type is sniffed from the start of the entry
+                archive.read_entry_at(name, 0, &mut |reader| {
+                    reader.take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+                    Ok(())
+                })?;
+
+                Ok(entry)
+            })?;
+
+            let key = format!("{path}{CONTAINER_SEP}{name}");
+
+            Ok(Asset {
+                size: entry.size,
+                modified,
+                etag: format!(
+                    "\"{:x}-{:x}-{:x}\"",
+                    meta.len(),
+                    nanos(modified),
+                    crc32fast::hash(key.as_bytes())
+                ),
+                content_type: mime::detect_bytes(&head, name).mime,
+            })
         }
-        _ => {
-            let file = tokio::fs::File::open(&path).await?;
-            let stream = ReaderStream::new(file);
+    }
+}
+
+/// Reads a whole file or entry into memory, refusing ones over `limit` since
+/// an entry's size in the index isn't to be trusted.
+fn read_all(cache: &ArchiveCache, target: &Target, limit: u64) -> Result<Vec<u8>> {
+    let mut res = vec![];
 
-            Ok(Body::from_stream(stream))
+    match target {
+        Target::File(path) => {
+            File::open(path)?.take(limit + 1).read_to_end(&mut res)?;
         }
+        Target::Entry {
+            path,
+            name,
+            container,
+        } => {
+            cache.with(path, container.as_deref(), |archive| {
+                archive.read_entry(name, &mut |reader| {
+                    reader.take(limit + 1).read_to_end(&mut res)?;
+                    Ok(())
+                })
+            })?;
+        }
+    }
+
+    if res.len() as u64 > limit {
+        return Err(source_too_large());
+    }
+
+    Ok(res)
+}
+
+fn source_too_large() -> Error {
+    Error::new(format!(
+        "too large for a thumbnail, the limit is {} MiB",
+        thumbnail::MAX_SOURCE_LEN / 1024 / 1024
+    ))
+    .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+}
+
+fn send_parts(
+    cache: &ArchiveCache,
+    target: &Target,
+    parts: Vec<Part>,
+    tx: &mpsc::Sender<Result<Bytes>>,
+) -> Result<()> {
+    match target {
+        Target::File(path) => {
+            let mut file = File::open(path)?;
+
+            send_each(parts, tx, |range| {
+                file.seek(SeekFrom::Start(range.start))?;
+                send_range(&mut file, range.len(), tx)
+            })
+        }
+        Target::Entry {
+            path,
+            name,
+            container,
+        } => cache.with(path, container.as_deref(), |archive| {
+            send_each(parts, tx, |range| {
+                let mut open = true;
+
+                archive.read_entry_at(name, range.start, &mut |reader| {
+                    open = send_range(reader, range.len(), tx)?;
+                    Ok(())
+                })?;
+
+                Ok(open)
+            })
+        }),
     }
 }
+
+fn send_each(
+    parts: Vec<Part>,
+    tx: &mpsc::Sender<Result<Bytes>>,
+    mut send: impl FnMut(ByteRange) -> Result<bool>,
+) -> Result<()> {
+    for part in parts {
+        let open = match part {
+            Part::Bytes(bytes) => tx.blocking_send(Ok(bytes)).is_ok(),
+            Part::Range(range) => send(range)?,
+        };
+
+        if !open {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Streams `len` bytes from the reader, returns false once the client is gone.
+fn send_range(reader: &mut dyn Read, len: u64, tx: &mpsc::Sender<Result<Bytes>>) -> Result<bool> {
+    let mut reader = reader.take(len);
+    let mut buf = vec![0u8; CHUNK_LEN];
+
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        if tx
+            .blocking_send(Ok(Bytes::copy_from_slice(&buf[..read])))
+            .is_err()
+        {
+            return Ok(false);
+        }
+    }
+
+    if reader.limit() > 0 {
+        return Err(Error::new("unexpected end of file"));
+    }
+
+    Ok(true)
+}
+
+fn boundary() -> String {
+    format!("{:016x}", nanos(SystemTime::now()))
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|it| it.as_nanos())
+        .unwrap_or_default()
+}
@@ -0,0 +1,65 @@
+use crate::download::queue::Enqueue;
+use crate::error::{Error, Result};
+use crate::utils::{BridgeRequest, State};
+use axum::Json;
+use axum::extract::State as StateExtractor;
+use axum::response::IntoResponse;
+use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
+
+pub async fn handler(
+    state: StateExtractor<State>,
+    Json(mut req): Json<BridgeRequest<JsonValue>>,
+) -> Result<impl IntoResponse> {
+    match req.action.as_str() {
+        "enqueue" => {
+            let items: Vec<Enqueue> = from_json(req.data["items"].take())?;
+            let res = state.downloads.enqueue(items)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list" => {
+            let res = state.downloads.list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "pause" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.downloads.pause(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "resume" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.downloads.resume(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "cancel" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.downloads.cancel(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "set-priority" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let priority: i32 = from_json(req.data["priority"].take())?;
+            let res = state.downloads.set_priority(id, priority)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "clear" => {
+            let ids: Option<Vec<u64>> = from_json(req.data["ids"].take())?;
+            let res = state.downloads.clear(ids)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        _ => Err(Error::new(format!("invalid action: {}", req.action))),
+    }
+}
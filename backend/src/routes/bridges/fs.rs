@@ -1,5 +1,12 @@
+use crate::archive::ListOptions;
 use crate::error::{Error, Result};
-use crate::fs::{download_file, read_archive, stats};
+use crate::fs::{
+    Mode, WriteOptions, chmod, disk_info, read_archive, read_dir, set_times, stats, symlink,
+    write_file,
+};
+use crate::hash::{Algorithm, hash_file};
+use crate::routes::bridges::jobs;
+use crate::trash;
 use crate::utils::{BridgeRequest, State};
 use axum::Json;
 use axum::extract::State as StateExtractor;
@@ -7,6 +14,7 @@ use axum::response::IntoResponse;
 use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
 use std::fs;
 use std::path::PathBuf;
+use tokio::task::spawn_blocking;
 
 pub async fn handler(
     state: StateExtractor<State>,
@@ -15,19 +23,53 @@ pub async fn handler(
     match req.action.as_str() {
         "stats" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
-            let res = stats(&path)?;
+            let sniff: Option<bool> = from_json(req.data["sniff"].take())?;
+            let res = stats(&path, sniff.unwrap_or_default())?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "chmod" => {
+            let path: PathBuf = from_json(req.data["path"].take())?;
+            let mode: Mode = from_json(req.data["mode"].take())?;
+
+            chmod(&path, mode.bits()?)?;
+            let res = stats(&path, false)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "set-times" => {
+            let path: PathBuf = from_json(req.data["path"].take())?;
+            let modified_time: Option<u64> = from_json(req.data["modifiedTime"].take())?;
+            let accessed_time: Option<u64> = from_json(req.data["accessedTime"].take())?;
+
+            set_times(&path, modified_time, accessed_time)?;
+            let res = stats(&path, false)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "symlink" => {
+            let target: PathBuf = from_json(req.data["target"].take())?;
+            let path: PathBuf = from_json(req.data["path"].take())?;
+
+            symlink(&target, &path)?;
+            let res = stats(&path, false)?;
 
             Ok(Json(to_json(&res)?))
         }
 
         "read-dir" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
-            let mut res = vec![];
+            let detailed: bool = from_json(req.data["detailed"].take()).unwrap_or(false);
+            let entries = read_dir(&path, detailed)?;
 
-            for entry in fs::read_dir(path)? {
-                res.push(entry?.file_name().to_string_lossy().to_string());
+            if detailed {
+                return Ok(Json(to_json(&entries)?));
             }
 
+            let res: Vec<_> = entries.into_iter().map(|it| it.name).collect();
             Ok(Json(to_json(&res)?))
         }
 
@@ -65,6 +107,34 @@ pub async fn handler(
             Ok(Json(JsonValue::Null))
         }
 
+        "trash" => {
+            let path: PathBuf = from_json(req.data["path"].take())?;
+            let res = trash::trash(&path)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list-trash" => {
+            let res = trash::list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "restore" => {
+            let path: PathBuf = from_json(req.data["path"].take())?;
+            let dst: Option<PathBuf> = from_json(req.data["dst"].take())?;
+            let res = trash::restore(&path, dst.as_deref())?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "empty-trash" => {
+            let paths: Option<Vec<PathBuf>> = from_json(req.data["paths"].take())?;
+            let res = trash::empty(paths)?;
+
+            Ok(Json(to_json(res)?))
+        }
+
         "copy-file" => {
             let src: PathBuf = from_json(req.data["src"].take())?;
             let dst: PathBuf = from_json(req.data["dst"].take())?;
@@ -73,6 +143,18 @@ pub async fn handler(
             Ok(Json(JsonValue::Null))
         }
 
+        "copy" => {
+            let res = jobs::start(&state, "fs/copy", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "move" => {
+            let res = jobs::start(&state, "fs/move", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
         "read-text-file" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
             let res = fs::read_to_string(path)?;
@@ -83,23 +165,82 @@ pub async fn handler(
         "write-text-file" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
             let data: String = from_json(req.data["data"].take())?;
+            let options: Option<WriteOptions> = from_json(req.data["options"].take())?;
 
-            fs::write(path, data)?;
-            Ok(Json(JsonValue::Null))
+            write_file(&path, data.as_bytes(), &options.unwrap_or_default())?;
+            let res = stats(&path, false)?;
+
+            Ok(Json(to_json(&res)?))
         }
 
         "download-file" => {
-            let url: String = from_json(req.data["url"].take())?;
+            let res = jobs::start(&state, "fs/download", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "du" => {
+            let res = jobs::start(&state, "fs/du", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "disk-info" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
+            let res = spawn_blocking(move || disk_info(&path)).await??;
 
-            download_file(&state.http_client, &url, &path).await?;
-            Ok(Json(JsonValue::Null))
+            Ok(Json(to_json(&res)?))
         }
 
-        "read-archive" => {
+        "hash" => {
             let path: PathBuf = from_json(req.data["path"].take())?;
-            let container: String = from_json(req.data["container"].take())?;
-            let res = read_archive(&path, &container)?;
+            let algorithm: Option<Algorithm> = from_json(req.data["algorithm"].take())?;
+            let algorithm = algorithm.unwrap_or_default();
+
+            let res = spawn_blocking(move || hash_file(&path, algorithm, |_| Ok(()))).await??;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "find-duplicates" => {
+            let res = jobs::start(&state, "fs/find-duplicates", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "read-archive" => {
+            let path: String = from_json(req.data["path"].take())?;
+            let container: Option<String> = from_json(req.data["container"].take())?;
+            let detailed: bool = from_json(req.data["detailed"].take()).unwrap_or(false);
+            let options: Option<ListOptions> = from_json(req.data["options"].take())?;
+
+            let cache = state.archives.clone();
+            let entries = spawn_blocking(move || {
+                read_archive(
+                    &cache,
+                    &path,
+                    container.as_deref(),
+                    &options.unwrap_or_default(),
+                )
+            })
+            .await??;
+
+            if detailed {
+                return Ok(Json(to_json(&entries)?));
+            }
+
+            let res: Vec<_> = entries.into_iter().map(|it| it.name).collect();
+            Ok(Json(to_json(&res)?))
+        }
+
+        "extract" => {
+            let res = jobs::start(&state, "archive/extract", req.data)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "create-archive" => {
+            let res = jobs::start(&state, "archive/create", req.data)?;
 
             Ok(Json(to_json(&res)?))
         }
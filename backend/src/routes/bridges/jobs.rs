@@ -0,0 +1,286 @@
+use crate::archive::create::{CreateOptions, create};
+use crate::archive::extract::{ExtractOptions, extract};
+use crate::download::{DownloadOptions, download};
+use crate::error::{Error, Result};
+use crate::fs::{CopyOptions, copy, du, move_entry};
+use crate::hash::{Algorithm, find_duplicates, hash_file};
+use crate::jobs::{Job, Status};
+use crate::utils::{BridgeRequest, State};
+use axum::Json;
+use axum::extract::{Query, State as StateExtractor};
+use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use futures::stream;
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+pub async fn handler(
+    state: StateExtractor<State>,
+    Json(mut req): Json<BridgeRequest<JsonValue>>,
+) -> Result<impl IntoResponse> {
+    match req.action.as_str() {
+        "start" => {
+            let kind: String = from_json(req.data["kind"].take())?;
+            let res = start(&state, &kind, req.data["data"].take())?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "status" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.jobs.get(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list" => {
+            let res = state.jobs.list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "cancel" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.jobs.cancel(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "wait" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let timeout: Option<u64> = from_json(req.data["timeoutMs"].take())?;
+            let res = state
+                .jobs
+                .wait(id, timeout.map(Duration::from_millis))
+                .await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        _ => Err(Error::new(format!("invalid action: {}", req.action))),
+    }
+}
+
+/// Starts a job by kind, `data` holds the same fields as the bridge action it mirrors.
+pub fn start(state: &State, kind: &str, mut data: JsonValue) -> Result<Job> {
+    match kind {
+        "fs/copy" => {
+            let src: PathBuf = from_json(data["src"].take())?;
+            let dst: PathBuf = from_json(data["dst"].take())?;
+            let options: Option<CopyOptions> = from_json(data["options"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let report = copy(&src, &dst, &options.unwrap_or_default(), job)?;
+                Ok(to_json(&report)?)
+            })
+        }
+
+        "fs/move" => {
+            let src: PathBuf = from_json(data["src"].take())?;
+            let dst: PathBuf = from_json(data["dst"].take())?;
+            let options: Option<CopyOptions> = from_json(data["options"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let report = move_entry(&src, &dst, &options.unwrap_or_default(), job)?;
+                Ok(to_json(&report)?)
+            })
+        }
+
+        "fs/download" => {
+            let url: String = from_json(data["url"].take())?;
+            let path: PathBuf = from_json(data["path"].take())?;
+            let options: Option<DownloadOptions> = from_json(data["options"].take())?;
+            let http_client = state.http_client.clone();
+
+            state.jobs.spawn(kind, move |job| async move {
+                let options = options.unwrap_or_default();
+                let report = download(&http_client, &url, &path, &options, None, &job).await?;
+                Ok(to_json(&report)?)
+            })
+        }
+
+        "fs/du" => {
+            let path: PathBuf = from_json(data["path"].take())?;
+            let depth: Option<usize> = from_json(data["depth"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let res = du(&path, depth.unwrap_or(1), job)?;
+                Ok(to_json(&res)?)
+            })
+        }
+
+        "fs/hash" => {
+            let path: PathBuf = from_json(data["path"].take())?;
+            let algorithm: Option<Algorithm> = from_json(data["algorithm"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let total = std::fs::metadata(&path)?.len();
+                let mut done = 0;
+
+                let res = hash_file(&path, algorithm.unwrap_or_default(), |read| {
+                    done += read as u64;
+                    job.progress(done, Some(total), None);
+                    job.check()
+                })?;
+
+                Ok(to_json(&res)?)
+            })
+        }
+
+        "fs/find-duplicates" => {
+            let path: PathBuf = from_json(data["path"].take())?;
+            let algorithm: Option<Algorithm> = from_json(data["algorithm"].take())?;
+            let min_size: Option<u64> = from_json(data["minSize"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let res = find_duplicates(
+                    &path,
+                    algorithm.unwrap_or_default(),
+                    min_size.unwrap_or(1),
+                    job,
+                )?;
+                Ok(to_json(&res)?)
+            })
+        }
+
+        "archive/extract" => {
+            let path: String = from_json(data["path"].take())?;
+            let container: Option<String> = from_json(data["container"].take())?;
+            let dst: PathBuf = from_json(data["dst"].take())?;
+            let options: Option<ExtractOptions> = from_json(data["options"].take())?;
+            let max_spool = state.archives.max_spool();
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let report = extract(
+                    &path,
+                    container.as_deref(),
+                    max_spool,
+                    &dst,
+                    &options.unwrap_or_default(),
+                    job,
+                )?;
+                Ok(to_json(&report)?)
+            })
+        }
+
+        "archive/create" => {
+            let paths: Vec<PathBuf> = from_json(data["paths"].take())?;
+            let dst: PathBuf = from_json(data["dst"].take())?;
+            let options: Option<CreateOptions> = from_json(data["options"].take())?;
+
+            state.jobs.spawn_blocking(kind, move |job| {
+                let report = create(&paths, &dst, &options.unwrap_or_default(), job)?;
+                Ok(to_json(&report)?)
+            })
+        }
+
+        "db/sqlite/execute" | "db/sqlite/fetch" => {
+            let sql: String = from_json(data["sql"].take())?;
+            let client = state
+                .sqlite_client
+                .clone()
+                .ok_or_else(|| Error::new("not connected"))?;
+            let fetch = kind == "db/sqlite/fetch";
+
+            // dropping the query future is what stops it
+            state.jobs.spawn(kind, move |job| async move {
+                let query = async {
+                    match fetch {
+                        true => Ok(to_json(client.fetch(&sql).await?)?),
+                        false => Ok(to_json(client.execute(&sql).await?)?),
+                    }
+                };
+
+                tokio::select! {
+                    res = query => res,
+                    _ = job.token.cancelled() => Err(Error::new("cancelled")),
+                }
+            })
+        }
+
+        _ => Err(Error::new(format!("invalid job kind: {kind}"))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsParams {
+    pub id: Option<u64>,
+}
+
+struct Events {
+    id: Option<u64>,
+    pending: VecDeque<Job>,
+    receiver: tokio::sync::broadcast::Receiver<Job>,
+    state: State,
+    done: bool,
+}
+
+/// Streams job updates as server-sent events, all jobs or only the one in `id`.
+/// Following a single job ends the stream once it has finished.
+pub async fn events(
+    state: StateExtractor<State>,
+    Query(params): Query<EventsParams>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let receiver = state.jobs.subscribe();
+
+    // the current state goes first so nothing from before subscribing is missed
+    let pending = match params.id {
+        Some(id) => VecDeque::from([state.jobs.get(id)?]),
+        None => state.jobs.list()?.into(),
+    };
+
+    let events = Events {
+        id: params.id,
+        pending,
+        receiver,
+        state: state.0.clone(),
+        done: false,
+    };
+
+    let stream = stream::unfold(events, |mut events| async move {
+        if events.done {
+            return None;
+        }
+
+        let job = loop {
+            if let Some(job) = events.pending.pop_front() {
+                break job;
+            }
+
+            let res = tokio::select! {
+                res = events.receiver.recv() => res,
+                _ = events.state.shutdown.cancelled() => return None,
+            };
+
+            match res {
+                Ok(job) if events.id.is_none_or(|id| id == job.id) => break job,
+                Ok(_) => continue,
+                // updates were dropped, catch up from the registry
+                Err(RecvError::Lagged(_)) => match events.id {
+                    Some(id) => events.pending.extend(events.state.jobs.get(id).ok()),
+                    None => events
+                        .pending
+                        .extend(events.state.jobs.list().unwrap_or_default()),
+                },
+                Err(RecvError::Closed) => return None,
+            }
+        };
+
+        events.done = events.id.is_some() && job.status != Status::Running;
+
+        let event = Event::default()
+            .event("job")
+            .json_data(&job)
+            .unwrap_or_else(|_| Event::default().event("job"));
+
+        Some((Ok(event), events))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
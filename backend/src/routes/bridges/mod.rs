@@ -1,14 +1,18 @@
 pub mod asset;
 pub mod config;
 pub mod db;
+pub mod downloads;
 pub mod fs;
+pub mod jobs;
 pub mod process;
 pub mod proxy;
+pub mod schedule;
 pub mod store;
+pub mod terminal;
 
 use crate::utils::State;
 use axum::Router;
-use axum::routing::{any, post};
+use axum::routing::{any, get, post};
 
 pub fn router() -> Router<State> {
     Router::new()
@@ -17,7 +21,16 @@ pub fn router() -> Router<State> {
         .route("/db/mongo", post(db::mongo::handler))
         .route("/db/sqlite", post(db::sqlite::handler))
         .route("/store", post(store::handler))
+        .route("/jobs", post(jobs::handler))
+        .route("/jobs/events", get(jobs::events))
+        .route("/downloads", post(downloads::handler))
         .route("/process", post(process::handler))
+        .route("/process/events", get(process::events))
+        .route("/process/ws", any(process::ws))
+        .route("/process/system", get(process::system))
+        .route("/schedule", post(schedule::handler))
+        .route("/terminal", post(terminal::handler))
+        .route("/terminal/ws", any(terminal::ws))
         .route("/proxy/{*url}", any(proxy::handler))
         .route("/asset/{*path}", any(asset::handler))
 }
@@ -1,40 +1,201 @@
 use crate::error::{Error, Result};
-use crate::utils::BridgeRequest;
+use crate::presets::{RunOptions, run};
+use crate::process::{Event, OutputOptions, Signal, SpawnOptions, open_detached, output, reveal};
+use crate::system::{cpu_times, kill_pid, ps, system_info};
+use crate::utils::{BridgeRequest, State};
 use axum::Json;
-use axum::response::IntoResponse;
-use serde::Serialize;
-use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
-use tokio::process::Command;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State as StateExtractor};
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures::stream::{self, Stream};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue, from_value as from_json, to_value as to_json};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::spawn_blocking;
+use tokio::time::{MissedTickBehavior, interval_at, sleep};
 
-pub async fn handler(Json(mut req): Json<BridgeRequest<JsonValue>>) -> Result<impl IntoResponse> {
+/// How long cpu usage is measured for when the client doesn't say.
+const DEFAULT_SAMPLE_MS: u64 = 250;
+
+pub async fn handler(
+    state: StateExtractor<State>,
+    Json(mut req): Json<BridgeRequest<JsonValue>>,
+) -> Result<impl IntoResponse> {
     match req.action.as_str() {
         "open" => {
             let path: String = from_json(req.data["path"].take())?;
             let using: Option<String> = from_json(req.data["using"].take())?;
 
+            // an application can be any program
             if using.is_some() {
-                // ! this inherits the parent handles and doesn't free the port
-                // https://stackoverflow.com/questions/75767291
-                // open::with_detached(path, using)?;
-                unimplemented!();
-            } else {
-                open::that(path)?;
+                state.config.read()?.commands.allow_arbitrary()?;
             }
 
+            open_detached(path.as_ref(), using.as_deref())?;
+            Ok(Json(JsonValue::Null))
+        }
+
+        "reveal" => {
+            let path: PathBuf = from_json(req.data["path"].take())?;
+
+            spawn_blocking(move || reveal(&path)).await??;
             Ok(Json(JsonValue::Null))
         }
 
         "output" => {
+            state.config.read()?.commands.allow_arbitrary()?;
+
+            let cmd: String = from_json(req.data["cmd"].take())?;
+            let args: Option<Vec<String>> = from_json(req.data["args"].take())?;
+            let options: Option<OutputOptions> = from_json(req.data["options"].take())?;
+
+            let res = output(
+                &cmd,
+                &args.unwrap_or_default(),
+                &options.unwrap_or_default(),
+            )
+            .await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "spawn" => {
+            state.config.read()?.commands.allow_arbitrary()?;
+
             let cmd: String = from_json(req.data["cmd"].take())?;
-            let args: Vec<String> = from_json(req.data["args"].take())?;
-            let output = Command::new(cmd).args(args).output().await?;
+            let args: Option<Vec<String>> = from_json(req.data["args"].take())?;
+            let options: Option<SpawnOptions> = from_json(req.data["options"].take())?;
+
+            let res = state.processes.spawn(
+                &cmd,
+                &args.unwrap_or_default(),
+                &options.unwrap_or_default(),
+            )?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "presets" => {
+            let res = state.config.read()?.commands.presets.clone();
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "run-preset" => {
+            let name: String = from_json(req.data["name"].take())?;
+            let params: Option<Map<String, JsonValue>> = from_json(req.data["params"].take())?;
+            let options: Option<RunOptions> = from_json(req.data["options"].take())?;
+
+            let preset = state
+                .config
+                .read()?
+                .commands
+                .presets
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| Error::new(format!("preset not found: {name}")))?;
+
+            let res = run(
+                &preset,
+                &params.unwrap_or_default(),
+                options.unwrap_or_default(),
+            )
+            .await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "status" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.processes.get(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list" => {
+            let res = state.processes.list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "write-stdin" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let data: String = from_json(req.data["data"].take())?;
+            let close: Option<bool> = from_json(req.data["close"].take())?;
+
+            state
+                .processes
+                .write_stdin(id, data.as_bytes(), close.unwrap_or_default())
+                .await?;
+            Ok(Json(JsonValue::Null))
+        }
+
+        "kill" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let signal: Option<Signal> = from_json(req.data["signal"].take())?;
+            let res = state.processes.kill(id, signal.unwrap_or_default())?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "wait" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let timeout: Option<u64> = from_json(req.data["timeoutMs"].take())?;
+            let res = state
+                .processes
+                .wait(id, timeout.map(Duration::from_millis))
+                .await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "remove" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.processes.remove(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
 
-            let res = Output {
-                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                status: output.status.code(),
+        "ps" => {
+            let interval: Option<u64> = from_json(req.data["intervalMs"].take())?;
+            let res = ps(Duration::from_millis(interval.unwrap_or(DEFAULT_SAMPLE_MS))).await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "kill-pid" => {
+            state.config.read()?.commands.allow_arbitrary()?;
+
+            let pid: u32 = from_json(req.data["pid"].take())?;
+            let signal: Option<Signal> = from_json(req.data["signal"].take())?;
+
+            kill_pid(pid, signal.unwrap_or_default())?;
+            Ok(Json(JsonValue::Null))
+        }
+
+        "system-info" => {
+            let interval: Option<u64> = from_json(req.data["intervalMs"].take())?;
+            let interval = Duration::from_millis(interval.unwrap_or(DEFAULT_SAMPLE_MS));
+
+            // cpu usage needs two samples
+            let prev = match interval.is_zero() {
+                true => None,
+                false => {
+                    let prev = cpu_times()?;
+                    sleep(interval).await;
+                    Some(prev)
+                }
             };
 
+            let (res, _) = system_info(prev.as_ref())?;
+
             Ok(Json(to_json(&res)?))
         }
 
@@ -42,9 +203,165 @@ pub async fn handler(Json(mut req): Json<BridgeRequest<JsonValue>>) -> Result<im
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct Output {
-    pub stdout: String,
-    pub stderr: String,
-    pub status: Option<i32>,
+#[derive(Debug, Deserialize)]
+pub struct OutputParams {
+    pub id: u64,
+    /// Only output after this sequence number is replayed, all of it by default.
+    pub since: Option<u64>,
+}
+
+/// Streams a process's output as server-sent events until it exits.
+pub async fn events(
+    state: StateExtractor<State>,
+    Query(params): Query<OutputParams>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<SseEvent, Infallible>>>> {
+    let (history, receiver) = state
+        .processes
+        .subscribe(params.id, params.since.unwrap_or_default())?;
+
+    let stream = output_stream(history, receiver).map(|event| {
+        let name = match event {
+            Event::Output { .. } => "output",
+            Event::Exit { .. } => "exit",
+        };
+
+        Ok(SseEvent::default()
+            .event(name)
+            .id(event.seq().to_string())
+            .json_data(&event)
+            .unwrap_or_else(|_| SseEvent::default().event(name)))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Same as [`events`] over a websocket, text sent by the client goes to stdin.
+pub async fn ws(
+    state: StateExtractor<State>,
+    Query(params): Query<OutputParams>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response> {
+    let (history, receiver) = state
+        .processes
+        .subscribe(params.id, params.since.unwrap_or_default())?;
+
+    let processes = state.processes.clone();
+
+    Ok(upgrade.on_upgrade(move |socket: WebSocket| async move {
+        let (mut sender, mut incoming) = socket.split();
+
+        let send = async {
+            let mut output = Box::pin(output_stream(history, receiver));
+
+            while let Some(event) = output.next().await {
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+
+                if sender.send(Message::Text(text.into())).await.is_err() {
+                    return;
+                }
+            }
+
+            sender.send(Message::Close(None)).await.ok();
+        };
+
+        let receive = async {
+            while let Some(Ok(message)) = incoming.next().await {
+                let data = match message {
+                    Message::Text(text) => text.as_str().as_bytes().to_vec(),
+                    Message::Binary(data) => data.to_vec(),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+
+                processes.write_stdin(params.id, &data, false).await.ok();
+            }
+        };
+
+        tokio::select! {
+            _ = send => {}
+            _ = receive => {}
+        }
+    }))
+}
+
+/// Replays `history` and follows `receiver`, ending after the exit event.
+fn output_stream(history: Vec<Event>, receiver: Receiver<Event>) -> impl Stream<Item = Event> {
+    let last = history.last().map(|it| it.seq()).unwrap_or_default();
+    let pending: VecDeque<Event> = history.into();
+
+    stream::unfold(
+        (pending, receiver, last, false),
+        |(mut pending, mut receiver, mut last, done)| async move {
+            if done {
+                return None;
+            }
+
+            let event = loop {
+                if let Some(event) = pending.pop_front() {
+                    break event;
+                }
+
+                match receiver.recv().await {
+                    // already sent from the history
+                    Ok(event) if event.seq() <= last => continue,
+                    Ok(event) => break event,
+                    // lines dropped for a slow reader are skipped
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            };
+
+            last = event.seq();
+            let done = matches!(event, Event::Exit { .. });
+
+            Some((event, (pending, receiver, last, done)))
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemParams {
+    pub interval_ms: Option<u64>,
+}
+
+/// Streams `system-info` as server-sent events every `intervalMs`, 2s by default.
+/// The first one goes out right away, without the cpu usage.
+pub async fn system(
+    state: StateExtractor<State>,
+    Query(params): Query<SystemParams>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<SseEvent, Infallible>>>> {
+    let period = Duration::from_millis(params.interval_ms.unwrap_or(2000).max(500));
+    let (info, cpu) = system_info(None)?;
+
+    let mut ticker = interval_at(tokio::time::Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let stream = stream::unfold(
+        (Some(info), cpu, ticker, state.shutdown.clone()),
+        |(pending, prev, mut ticker, shutdown)| async move {
+            let (info, cpu) = match pending {
+                Some(info) => (info, prev),
+                None => {
+                    tokio::select! {
+                        _ = ticker.tick() => {}
+                        _ = shutdown.cancelled() => return None,
+                    }
+
+                    system_info(Some(&prev)).ok()?
+                }
+            };
+
+            let event = SseEvent::default()
+                .event("system")
+                .json_data(&info)
+                .unwrap_or_else(|_| SseEvent::default().event("system"));
+
+            Some((Ok(event), (None, cpu, ticker, shutdown)))
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
@@ -0,0 +1,52 @@
+use crate::error::{Error, Result};
+use crate::schedule::Task;
+use crate::utils::{BridgeRequest, State};
+use axum::Json;
+use axum::extract::State as StateExtractor;
+use axum::response::IntoResponse;
+use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
+
+pub async fn handler(
+    state: StateExtractor<State>,
+    Json(mut req): Json<BridgeRequest<JsonValue>>,
+) -> Result<impl IntoResponse> {
+    match req.action.as_str() {
+        "add" => {
+            let name: String = from_json(req.data["name"].take())?;
+            let task: Task = from_json(req.data["task"].take())?;
+            let res = state.scheduler.add(name, task)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "remove" => {
+            let name: String = from_json(req.data["name"].take())?;
+            let res = state.scheduler.remove(&name)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list" => {
+            let res = state.scheduler.list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "run-now" => {
+            let name: String = from_json(req.data["name"].take())?;
+            let res = state.scheduler.run_now(&name)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "history" => {
+            let name: Option<String> = from_json(req.data["name"].take())?;
+            let limit: Option<usize> = from_json(req.data["limit"].take())?;
+            let res = state.scheduler.history(name.as_deref(), limit)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        _ => Err(Error::new(format!("invalid action: {}", req.action))),
+    }
+}
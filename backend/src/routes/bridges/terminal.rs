@@ -0,0 +1,190 @@
+use crate::error::{Error, Result};
+use crate::process::Signal;
+use crate::terminal::{Chunk, SpawnOptions};
+use crate::utils::{BridgeRequest, State};
+use axum::Json;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State as StateExtractor};
+use axum::response::{IntoResponse, Response};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, from_value as from_json, to_value as to_json};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+pub async fn handler(
+    state: StateExtractor<State>,
+    Json(mut req): Json<BridgeRequest<JsonValue>>,
+) -> Result<impl IntoResponse> {
+    match req.action.as_str() {
+        "spawn" => {
+            state.config.read()?.commands.allow_arbitrary()?;
+
+            let options: SpawnOptions = from_json(req.data["options"].take())?;
+            let res = state.terminals.spawn(&options)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "status" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.terminals.get(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "list" => {
+            let res = state.terminals.list()?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "input" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let data: String = from_json(req.data["data"].take())?;
+
+            state.terminals.input(id, data.into_bytes()).await?;
+            Ok(Json(JsonValue::Null))
+        }
+
+        "resize" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let rows: u16 = from_json(req.data["rows"].take())?;
+            let cols: u16 = from_json(req.data["cols"].take())?;
+            let res = state.terminals.resize(id, rows, cols)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "kill" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let signal: Option<Signal> = from_json(req.data["signal"].take())?;
+            let res = state.terminals.kill(id, signal.unwrap_or(Signal::Hup))?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "wait" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let timeout: Option<u64> = from_json(req.data["timeoutMs"].take())?;
+            let res = state
+                .terminals
+                .wait(id, timeout.map(Duration::from_millis))
+                .await?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        "remove" => {
+            let id: u64 = from_json(req.data["id"].take())?;
+            let res = state.terminals.remove(id)?;
+
+            Ok(Json(to_json(&res)?))
+        }
+
+        _ => Err(Error::new(format!("invalid action: {}", req.action))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SocketParams {
+    pub id: u64,
+}
+
+/// Control messages, sent as text frames. Terminal output goes both ways as binary frames.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Control {
+    Input {
+        data: String,
+    },
+    Resize {
+        rows: u16,
+        cols: u16,
+    },
+    Exit {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// Attaches to a terminal, replaying the scrollback first. Binary frames from
+/// the client are typed into it, text frames are [`Control`] messages.
+pub async fn ws(
+    state: StateExtractor<State>,
+    Query(params): Query<SocketParams>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response> {
+    let (scrollback, exit, mut receiver) = state.terminals.subscribe(params.id)?;
+    let terminals = state.terminals.clone();
+    let id = params.id;
+
+    Ok(upgrade.on_upgrade(move |socket: WebSocket| async move {
+        let (mut sender, mut incoming) = socket.split();
+
+        let send = async {
+            if !scrollback.is_empty() && sender.send(Message::Binary(scrollback)).await.is_err() {
+                return;
+            }
+
+            let mut next = exit;
+
+            loop {
+                let chunk = match next.take() {
+                    Some(chunk) => chunk,
+                    None => match receiver.recv().await {
+                        Ok(chunk) => chunk,
+                        // a slow client misses some output, the screen fixes itself on redraw
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+
+                let message = match chunk {
+                    Chunk::Data(data) => Message::Binary(data),
+                    Chunk::Exit { code, signal } => {
+                        let Ok(text) = serde_json::to_string(&Control::Exit { code, signal })
+                        else {
+                            break;
+                        };
+
+                        sender.send(Message::Text(text.into())).await.ok();
+                        break;
+                    }
+                };
+
+                if sender.send(message).await.is_err() {
+                    return;
+                }
+            }
+
+            sender.send(Message::Close(None)).await.ok();
+        };
+
+        let receive = async {
+            while let Some(Ok(message)) = incoming.next().await {
+                let res = match message {
+                    Message::Binary(data) => terminals.input(id, data.to_vec()).await,
+                    Message::Text(text) => match serde_json::from_str(text.as_str()) {
+                        Ok(Control::Input { data }) => terminals.input(id, data.into_bytes()).await,
+                        Ok(Control::Resize { rows, cols }) => {
+                            terminals.resize(id, rows, cols).map(|_| ())
+                        }
+                        _ => Ok(()),
+                    },
+                    Message::Close(_) => break,
+                    _ => Ok(()),
+                };
+
+                if res.is_err() {
+                    break;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = send => {}
+            _ = receive => {}
+        }
+    }))
+}
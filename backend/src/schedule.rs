@@ -0,0 +1,672 @@
+use crate::config::{Config, Schedule as ScheduleOptions};
+use crate::download::{DownloadOptions, download};
+use crate::error::{Error, Result};
+use crate::fs::{WriteOptions, read_state, write_file};
+use crate::jobs::{JobHandle, Jobs, Status, now};
+use crate::presets::{RunOptions, run as run_preset};
+use axum::http::StatusCode;
+use chrono::{Local, TimeZone};
+use cron::Schedule as CronSchedule;
+use reqwest::{Client as HttpClient, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::{
+    Map, Value as JsonValue, json, to_value as to_json, to_vec_pretty as to_json_vec,
+};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+/// The loop wakes up at least this often, so changes to the system clock are picked up.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Response bodies of http tasks are cut to this in the history.
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub trigger: Trigger,
+    pub action: Action,
+    #[serde(default)]
+    pub missed: Missed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Trigger {
+    /// Five fields from minutes, or six from seconds, in local time.
+    Cron(String),
+    /// Seconds between runs.
+    Every(u64),
+}
+
+impl Trigger {
+    /// The first time strictly after `time`, both in millis.
+    pub fn next_after(&self, time: u64) -> Result<u64> {
+        match self {
+            Self::Cron(expr) => {
+                let schedule = parse_cron(expr)?;
+                let after = Local
+                    .timestamp_millis_opt(time as i64)
+                    .single()
+                    .ok_or_else(|| Error::new(format!("invalid time: {time}")))?;
+
+                schedule
+                    .after(&after)
+                    .next()
+                    .map(|it| it.timestamp_millis() as u64)
+                    .ok_or_else(|| Error::new(format!("cron expression never fires: {expr}")))
+            }
+            Self::Every(0) => Err(Error::new("interval must be at least a second")),
+            Self::Every(secs) => secs
+                .checked_mul(1000)
+                .and_then(|it| it.checked_add(time))
+                .ok_or_else(|| Error::new(format!("interval is too long: {secs}s"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Action {
+    /// A command preset from the `commands` section.
+    #[serde(rename_all = "camelCase")]
+    Preset {
+        name: String,
+        #[serde(default)]
+        params: Map<String, JsonValue>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Download {
+        url: String,
+        path: PathBuf,
+        #[serde(default)]
+        options: DownloadOptions,
+    },
+    /// A string body is sent as is, anything else as json.
+    #[serde(rename_all = "camelCase")]
+    Http {
+        #[serde(default = "default_method")]
+        method: String,
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        body: Option<JsonValue>,
+        timeout_ms: Option<u64>,
+    },
+}
+
+impl Action {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Preset { .. } => "schedule/preset",
+            Self::Download { .. } => "schedule/download",
+            Self::Http { .. } => "schedule/http",
+        }
+    }
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// What happens to runs that were due while the backend wasn't running.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Missed {
+    #[default]
+    Skip,
+    /// Runs once right away, however many were missed.
+    RunOnce,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    Schedule,
+    Missed,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    Config,
+    Added,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub id: u64,
+    pub task: String,
+    pub reason: Reason,
+    pub status: Status,
+    pub job_id: Option<u64>,
+    pub result: Option<JsonValue>,
+    pub error: Option<String>,
+    pub started_time: u64,
+    pub finished_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskInfo {
+    pub name: String,
+    pub source: Source,
+    pub task: Task,
+    pub next_time: Option<u64>,
+    pub last_time: Option<u64>,
+    pub running: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Saved {
+    next_id: u64,
+    /// Tasks added through the bridge, the ones from the config stay there.
+    tasks: BTreeMap<String, Task>,
+    /// When each task was last due, missed runs are counted from here.
+    last_times: BTreeMap<String, u64>,
+    /// Oldest first.
+    runs: BTreeMap<String, Vec<Run>>,
+}
+
+#[derive(Debug)]
+struct Due {
+    time: u64,
+    reason: Reason,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    saved: Saved,
+    config_tasks: BTreeMap<String, Task>,
+    due: BTreeMap<String, Due>,
+}
+
+impl Inner {
+    fn task(&self, name: &str) -> Result<&Task> {
+        self.config_tasks
+            .get(name)
+            .or_else(|| self.saved.tasks.get(name))
+            .ok_or_else(|| Error::new(format!("task not found: {name}")))
+    }
+
+    fn tasks(&self) -> impl Iterator<Item = (&String, &Task, Source)> {
+        let config = self
+            .config_tasks
+            .iter()
+            .map(|(k, v)| (k, v, Source::Config));
+        let added = self.saved.tasks.iter().map(|(k, v)| (k, v, Source::Added));
+
+        config.chain(added)
+    }
+
+    fn running(&self, name: &str) -> bool {
+        self.saved
+            .runs
+            .get(name)
+            .is_some_and(|runs| runs.iter().any(|it| it.status == Status::Running))
+    }
+}
+
+/// Runs tasks on cron expressions or intervals, each run being a job. Tasks
+/// come from the config or are added at runtime, their history is saved on
+/// every change.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    inner: Arc<Mutex<Inner>>,
+    notify: Arc<Notify>,
+    path: PathBuf,
+    history: usize,
+    config: Arc<RwLock<Config>>,
+    http_client: HttpClient,
+    jobs: Jobs,
+}
+
+impl Scheduler {
+    pub fn new(
+        path: PathBuf,
+        options: ScheduleOptions,
+        config: Arc<RwLock<Config>>,
+        http_client: HttpClient,
+        jobs: Jobs,
+    ) -> Result<Self> {
+        let mut saved: Saved = read_state(&path, "schedule");
+
+        let time = now();
+
+        for run in saved.runs.values_mut().flatten() {
+            if run.status == Status::Running {
+                run.status = Status::Failed;
+                run.error = Some("interrupted by a restart".to_string());
+                run.finished_time = Some(time);
+                run.job_id = None;
+            }
+        }
+
+        // a config task could shadow one added before it was written in
+        saved
+            .tasks
+            .retain(|name, _| !options.tasks.contains_key(name));
+
+        let mut inner = Inner {
+            saved,
+            config_tasks: options.tasks.clone(),
+            due: BTreeMap::new(),
+        };
+
+        let tasks: Vec<(String, Task)> = inner
+            .tasks()
+            .map(|(name, task, _)| (name.clone(), task.clone()))
+            .collect();
+
+        for (name, task) in tasks {
+            let last = *inner.saved.last_times.entry(name.clone()).or_insert(time);
+            let next = task
+                .trigger
+                .next_after(last)
+                .map_err(|err| Error::new(format!("task {name}: {}", err.message)))?;
+
+            let due = match task.missed {
+                _ if next > time => Due {
+                    time: next,
+                    reason: Reason::Schedule,
+                },
+                Missed::RunOnce => Due {
+                    time,
+                    reason: Reason::Missed,
+                },
+                Missed::Skip => Due {
+                    time: task.trigger.next_after(time)?,
+                    reason: Reason::Schedule,
+                },
+            };
+
+            inner.due.insert(name, due);
+        }
+
+        inner
+            .saved
+            .last_times
+            .retain(|name, _| inner.due.contains_key(name));
+
+        let scheduler = Self {
+            inner: Arc::new(Mutex::new(inner)),
+            notify: Arc::default(),
+            path,
+            history: options.history().max(1),
+            config,
+            http_client,
+            jobs,
+        };
+
+        {
+            let inner = scheduler.inner.lock()?;
+            scheduler.save(&inner)?;
+        }
+
+        Ok(scheduler)
+    }
+
+    /// Starts the loop that runs tasks as they come due.
+    pub fn start(&self) {
+        let scheduler = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let wait = match scheduler.tick() {
+                    Ok(wait) => wait.min(MAX_SLEEP),
+                    Err(err) => {
+                        eprintln!("[schedule] {}", err.message);
+                        MAX_SLEEP
+                    }
+                };
+
+                tokio::select! {
+                    _ = scheduler.notify.notified() => {}
+                    _ = sleep(wait) => {}
+                }
+            }
+        });
+    }
+
+    pub fn add(&self, name: String, task: Task) -> Result<TaskInfo> {
+        let time = now();
+        let next = task.trigger.next_after(time)?;
+
+        let info = {
+            let mut inner = self.inner.lock()?;
+
+            if inner.task(&name).is_ok() {
+                return Err(Error::new(format!("task already exists: {name}"))
+                    .with_status(StatusCode::CONFLICT));
+            }
+
+            inner.saved.tasks.insert(name.clone(), task);
+            inner.saved.last_times.insert(name.clone(), time);
+            inner.due.insert(
+                name.clone(),
+                Due {
+                    time: next,
+                    reason: Reason::Schedule,
+                },
+            );
+
+            self.save(&inner)?;
+            self.info(&inner, &name)?
+        };
+
+        self.notify.notify_one();
+        Ok(info)
+    }
+
+    /// Removes a task added at runtime along with its history, a run in progress carries on.
+    pub fn remove(&self, name: &str) -> Result<TaskInfo> {
+        let mut inner = self.inner.lock()?;
+        let info = self.info(&inner, name)?;
+
+        if info.source == Source::Config {
+            return Err(Error::new(format!("task is defined in the config: {name}"))
+                .with_status(StatusCode::CONFLICT));
+        }
+
+        inner.saved.tasks.remove(name);
+        inner.saved.last_times.remove(name);
+        inner.saved.runs.remove(name);
+        inner.due.remove(name);
+
+        self.save(&inner)?;
+        Ok(info)
+    }
+
+    pub fn list(&self) -> Result<Vec<TaskInfo>> {
+        let inner = self.inner.lock()?;
+
+        inner
+            .tasks()
+            .map(|(name, _, _)| self.info(&inner, name))
+            .collect()
+    }
+
+    /// Runs a task right away, outside of its schedule.
+    pub fn run_now(&self, name: &str) -> Result<Run> {
+        let mut inner = self.inner.lock()?;
+        let task = inner.task(name)?.clone();
+
+        if inner.running(name) {
+            return Err(Error::new(format!("task is already running: {name}"))
+                .with_status(StatusCode::CONFLICT));
+        }
+
+        let run = self.run(&mut inner, name, &task, Reason::Manual)?;
+        self.save(&inner)?;
+
+        Ok(run)
+    }
+
+    /// Runs of one task or of all of them, newest first.
+    pub fn history(&self, name: Option<&str>, limit: Option<usize>) -> Result<Vec<Run>> {
+        let inner = self.inner.lock()?;
+
+        let mut runs: Vec<Run> = match name {
+            Some(name) => {
+                inner.task(name)?;
+                inner.saved.runs.get(name).cloned().unwrap_or_default()
+            }
+            None => inner.saved.runs.values().flatten().cloned().collect(),
+        };
+
+        runs.sort_by_key(|it| Reverse(it.id));
+        runs.truncate(limit.unwrap_or(usize::MAX));
+
+        Ok(runs)
+    }
+
+    fn info(&self, inner: &Inner, name: &str) -> Result<TaskInfo> {
+        let task = inner.task(name)?.clone();
+
+        Ok(TaskInfo {
+            name: name.to_string(),
+            source: match inner.config_tasks.contains_key(name) {
+                true => Source::Config,
+                false => Source::Added,
+            },
+            task,
+            next_time: inner.due.get(name).map(|it| it.time),
+            last_time: inner.saved.last_times.get(name).copied(),
+            running: inner.running(name),
+        })
+    }
+
+    /// Starts the tasks that are due, returning how long until the next one.
+    fn tick(&self) -> Result<Duration> {
+        let mut inner = self.inner.lock()?;
+        let time = now();
+
+        let due: Vec<(String, Reason)> = inner
+            .due
+            .iter()
+            .filter(|(_, due)| due.time <= time)
+            .map(|(name, due)| (name.clone(), due.reason))
+            .collect();
+
+        for (name, reason) in &due {
+            let task = inner.task(name)?.clone();
+
+            // a run that takes longer than the interval skips the ones that overlap it
+            if !inner.running(name)
+                && let Err(err) = self.run(&mut inner, name, &task, *reason)
+            {
+                eprintln!("[schedule] {name}: {}", err.message);
+            }
+
+            inner.saved.last_times.insert(name.clone(), time);
+
+            let next = match task.trigger.next_after(time) {
+                Ok(next) => next,
+                Err(err) => {
+                    eprintln!("[schedule] {name}: {}", err.message);
+                    time + MAX_SLEEP.as_millis() as u64
+                }
+            };
+
+            inner.due.insert(
+                name.clone(),
+                Due {
+                    time: next,
+                    reason: Reason::Schedule,
+                },
+            );
+        }
+
+        if !due.is_empty() {
+            self.save(&inner)?;
+        }
+
+        let next = inner.due.values().map(|it| it.time).min();
+        Ok(next.map_or(MAX_SLEEP, |it| {
+            Duration::from_millis(it.saturating_sub(time))
+        }))
+    }
+
+    fn run(&self, inner: &mut Inner, name: &str, task: &Task, reason: Reason) -> Result<Run> {
+        inner.saved.next_id += 1;
+        let id = inner.saved.next_id;
+
+        let scheduler = self.clone();
+        let action = task.action.clone();
+        let task_name = name.to_string();
+
+        let job = self.jobs.spawn(task.action.kind(), move |job| async move {
+            let res = scheduler.execute(&action, &job).await;
+            scheduler.finish(&task_name, id, &res, job.is_cancelled());
+            res
+        })?;
+
+        let run = Run {
+            id,
+            task: name.to_string(),
+            reason,
+            status: Status::Running,
+            job_id: Some(job.id),
+            result: None,
+            error: None,
+            started_time: job.started_time,
+            finished_time: None,
+        };
+
+        let runs = inner.saved.runs.entry(name.to_string()).or_default();
+        runs.push(run.clone());
+
+        let extra = runs.len().saturating_sub(self.history);
+        runs.drain(..extra);
+
+        Ok(run)
+    }
+
+    async fn execute(&self, action: &Action, job: &JobHandle) -> Result<JsonValue> {
+        match action {
+            Action::Preset { name, params } => {
+                let preset = self
+                    .config
+                    .read()?
+                    .commands
+                    .presets
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| Error::new(format!("preset not found: {name}")))?;
+
+                let output = tokio::select! {
+                    res = run_preset(&preset, params, RunOptions::default()) => res?,
+                    _ = job.token.cancelled() => return Err(Error::new("cancelled")),
+                };
+
+                match output.status {
+                    Some(0) => Ok(to_json(&output)?),
+                    _ if output.timed_out => Err(Error::new("preset timed out")),
+                    status => {
+                        let stderr = output.stderr.trim();
+                        let status = status.map_or("a signal".to_string(), |it| it.to_string());
+
+                        Err(Error::new(format!("preset exited with {status}: {stderr}")))
+                    }
+                }
+            }
+
+            Action::Download { url, path, options } => {
+                let report = download(&self.http_client, url, path, options, None, job).await?;
+                Ok(to_json(&report)?)
+            }
+
+            Action::Http {
+                method,
+                url,
+                headers,
+                body,
+                timeout_ms,
+            } => {
+                let method = Method::from_str(&method.to_uppercase())
+                    .map_err(|_| Error::new(format!("invalid method: {method}")))?;
+
+                let mut req = self.http_client.request(method, url);
+
+                for (key, value) in headers {
+                    req = req.header(key, value);
+                }
+
+                req = match body {
+                    Some(JsonValue::String(body)) => req.body(body.clone()),
+                    Some(body) => req.json(body),
+                    None => req,
+                };
+
+                if let Some(timeout) = timeout_ms {
+                    req = req.timeout(Duration::from_millis(*timeout));
+                }
+
+                let res = tokio::select! {
+                    res = req.send() => res?,
+                    _ = job.token.cancelled() => return Err(Error::new("cancelled")),
+                };
+
+                let status = res.status();
+                let mut body = res.text().await?;
+
+                if body.len() > MAX_BODY_LEN {
+                    let end = body.floor_char_boundary(MAX_BODY_LEN);
+                    body.truncate(end);
+                }
+
+                if !status.is_success() {
+                    return Err(Error::new(format!("request failed with {status}: {url}"))
+                        .with_status(StatusCode::BAD_GATEWAY));
+                }
+
+                Ok(json!({ "status": status.as_u16(), "body": body }))
+            }
+        }
+    }
+
+    fn finish(&self, name: &str, id: u64, res: &Result<JsonValue>, cancelled: bool) {
+        let res = (|| {
+            let mut inner = self.inner.lock()?;
+
+            let Some(run) = inner
+                .saved
+                .runs
+                .get_mut(name)
+                .and_then(|runs| runs.iter_mut().find(|it| it.id == id))
+            else {
+                return Ok(());
+            };
+
+            run.finished_time = Some(now());
+
+            match res {
+                _ if cancelled => run.status = Status::Cancelled,
+                Ok(value) => {
+                    run.status = Status::Completed;
+                    run.result = Some(value.clone());
+                }
+                Err(err) => {
+                    run.status = Status::Failed;
+                    run.error = Some(err.message.clone());
+                }
+            }
+
+            self.save(&inner)
+        })();
+
+        if let Err(err) = res {
+            eprintln!("[schedule] {}", err.message);
+        }
+    }
+
+    fn save(&self, inner: &Inner) -> Result<()> {
+        let options = WriteOptions {
+            atomic: true,
+            create_parents: true,
+            ..Default::default()
+        };
+
+        write_file(&self.path, &to_json_vec(&inner.saved)?, &options)
+    }
+}
+
+/// Standard five field expressions get a leading zero for the seconds.
+fn parse_cron(expr: &str) -> Result<CronSchedule> {
+    let expr = match expr.split_whitespace().count() {
+        5 => format!("0 {expr}"),
+        _ => expr.to_string(),
+    };
+
+    CronSchedule::from_str(&expr)
+        .map_err(|err| Error::new(format!("invalid cron expression: {expr}: {err}")))
+}
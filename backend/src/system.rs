@@ -0,0 +1,411 @@
+use crate::error::{Error, Result};
+use crate::process::Signal;
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    /// Empty for kernel threads.
+    pub cmdline: Vec<String>,
+    pub user: Option<String>,
+    pub state: String,
+    /// Percent of one core, a busy process with several threads can go past 100.
+    pub cpu: f64,
+    /// Resident bytes.
+    pub memory: u64,
+    pub threads: u32,
+    pub start_time: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemInfo {
+    pub hostname: String,
+    pub os: Os,
+    pub cpu_count: usize,
+    /// Percent of all cores since the previous sample, not set without one.
+    pub cpu_usage: Option<f64>,
+    pub load_average: [f64; 3],
+    pub memory: Memory,
+    /// Seconds since boot.
+    pub uptime: u64,
+    pub boot_time: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Os {
+    pub name: Option<String>,
+    pub kernel: Option<String>,
+    pub arch: String,
+}
+
+/// All in bytes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Memory {
+    pub total: u64,
+    pub available: u64,
+    pub used: u64,
+    pub swap_total: u64,
+    pub swap_used: u64,
+}
+
+/// Cumulative cpu time of the machine, two of them give the usage in between.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    pub fn usage_since(&self, prev: &CpuTimes) -> Option<f64> {
+        let total = self.total.checked_sub(prev.total)?;
+        let busy = self.busy.checked_sub(prev.busy)?;
+
+        (total > 0).then(|| round(busy as f64 * 100.0 / total as f64))
+    }
+}
+
+/// Running processes, with their cpu usage measured over `interval`, or
+/// averaged over their lifetime when it's zero.
+#[cfg(target_os = "linux")]
+pub async fn ps(interval: Duration) -> Result<Vec<ProcessInfo>> {
+    use tokio::task::spawn_blocking;
+
+    let started = std::time::Instant::now();
+
+    let before = match interval.is_zero() {
+        true => None,
+        false => {
+            let before = spawn_blocking(|| linux::processes(false)).await??;
+            tokio::time::sleep(interval).await;
+            Some(before)
+        }
+    };
+
+    let after = spawn_blocking(|| linux::processes(true)).await??;
+    let elapsed = started.elapsed().as_secs_f64();
+    let clock = linux::clock_ticks() as f64;
+    let uptime = linux::uptime()?;
+
+    Ok(after
+        .into_values()
+        .map(|sample| {
+            let (ticks, secs) = match &before {
+                Some(before) => {
+                    // a reused pid is another process
+                    let prev = before
+                        .get(&sample.info.pid)
+                        .filter(|it| it.start_ticks == sample.start_ticks)
+                        .map_or(0, |it| it.ticks);
+
+                    (sample.ticks.saturating_sub(prev), elapsed)
+                }
+                None => (sample.ticks, uptime - sample.start_ticks as f64 / clock),
+            };
+
+            let mut info = sample.info;
+
+            if secs > 0.0 {
+                info.cpu = round(ticks as f64 / clock / secs * 100.0);
+            }
+
+            info
+        })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn ps(_: Duration) -> Result<Vec<ProcessInfo>> {
+    Err(unsupported())
+}
+
+#[cfg(target_os = "linux")]
+pub fn cpu_times() -> Result<CpuTimes> {
+    linux::cpu_times()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cpu_times() -> Result<CpuTimes> {
+    Err(unsupported())
+}
+
+/// The state of the machine, with the cpu usage since `prev` when given. The
+/// returned times are the ones to pass in next.
+#[cfg(target_os = "linux")]
+pub fn system_info(prev: Option<&CpuTimes>) -> Result<(SystemInfo, CpuTimes)> {
+    linux::system_info(prev)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn system_info(_: Option<&CpuTimes>) -> Result<(SystemInfo, CpuTimes)> {
+    Err(unsupported())
+}
+
+/// Signals a single process, unlike [`crate::process::send_signal`] which
+/// targets the group of a managed one.
+#[cfg(unix)]
+pub fn kill_pid(pid: u32, signal: Signal) -> Result<()> {
+    use axum::http::StatusCode;
+
+    // 0, -1 and other negatives reach more than one process, 1 is init
+    let target = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|it| *it > 1 && pid != std::process::id())
+        .ok_or_else(|| Error::new(format!("invalid pid: {pid}")))?;
+
+    if unsafe { libc::kill(target, signal.raw()) } != 0 {
+        let err = std::io::Error::last_os_error();
+
+        return Err(match err.raw_os_error() {
+            Some(libc::ESRCH) => {
+                Error::new(format!("process not found: {pid}")).with_status(StatusCode::NOT_FOUND)
+            }
+            Some(libc::EPERM) => Error::new(format!("not allowed to signal process: {pid}"))
+                .with_status(StatusCode::FORBIDDEN),
+            _ => err.into(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn kill_pid(pid: u32, _: Signal) -> Result<()> {
+    let status = std::process::Command::new("taskkill")
+        .args(["/F", "/PID", &pid.to_string()])
+        .status()?;
+
+    if !status.success() {
+        return Err(Error::new(format!("failed to kill process: {pid}")));
+    }
+
+    Ok(())
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> Error {
+    Error::new("system information is only available on linux")
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{CpuTimes, Memory, Os, ProcessInfo, SystemInfo};
+    use crate::error::{Error, Result};
+    use crate::fs::user_name;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    #[derive(Debug)]
+    pub struct Sample {
+        pub info: ProcessInfo,
+        /// User and system time, in clock ticks.
+        pub ticks: u64,
+        pub start_ticks: u64,
+    }
+
+    /// Every process in `/proc`, `details` also reads their command line and user.
+    pub fn processes(details: bool) -> Result<BTreeMap<u32, Sample>> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+        let clock = clock_ticks();
+        let boot_time = boot_time()?;
+
+        let mut users: HashMap<u32, Option<String>> = HashMap::new();
+        let mut res = BTreeMap::new();
+
+        for entry in fs::read_dir("/proc")?.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|it| it.parse().ok()) else {
+                continue;
+            };
+
+            // it can exit anywhere in between, what's gone is left out
+            let Ok(stat) = fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+
+            let Some(mut sample) = parse_stat(pid, &stat, page_size, clock, boot_time) else {
+                continue;
+            };
+
+            if details {
+                if let Ok(cmdline) = fs::read(entry.path().join("cmdline")) {
+                    sample.info.cmdline = cmdline
+                        .split(|it| *it == 0)
+                        .filter(|it| !it.is_empty())
+                        .map(|it| String::from_utf8_lossy(it).into_owned())
+                        .collect();
+                }
+
+                if let Ok(meta) = entry.metadata() {
+                    sample.info.user = users
+                        .entry(meta.uid())
+                        .or_insert_with(|| user_name(meta.uid()))
+                        .clone();
+                }
+            }
+
+            res.insert(pid, sample);
+        }
+
+        Ok(res)
+    }
+
+    fn parse_stat(
+        pid: u32,
+        stat: &str,
+        page_size: u64,
+        clock: u64,
+        boot_time: u64,
+    ) -> Option<Sample> {
+        // the name is in parens and can hold anything, parens and spaces too
+        let open = stat.find('(')?;
+        let close = stat.rfind(')')?;
+        let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+
+        // numbered as in proc(5), where the state is the third
+        let field = |n: usize| fields.get(n - 3).and_then(|it| it.parse::<u64>().ok());
+        let start_ticks = field(22)?;
+
+        Some(Sample {
+            info: ProcessInfo {
+                pid,
+                ppid: field(4)? as u32,
+                name: stat[open + 1..close].to_string(),
+                cmdline: vec![],
+                user: None,
+                state: fields.first()?.to_string(),
+                cpu: 0.0,
+                memory: field(24)? * page_size,
+                threads: field(20)? as u32,
+                start_time: boot_time + start_ticks * 1000 / clock,
+            },
+            ticks: field(14)? + field(15)?,
+            start_ticks,
+        })
+    }
+
+    pub fn system_info(prev: Option<&CpuTimes>) -> Result<(SystemInfo, CpuTimes)> {
+        let cpu = cpu_times()?;
+        let memory = meminfo()?;
+        let get = |key: &str| memory.get(key).copied().unwrap_or_default();
+
+        let loadavg = fs::read_to_string("/proc/loadavg")?;
+        let mut load_average = [0.0; 3];
+
+        for (value, field) in load_average.iter_mut().zip(loadavg.split_whitespace()) {
+            *value = field.parse().unwrap_or_default();
+        }
+
+        let (total, available) = (get("MemTotal"), get("MemAvailable"));
+        let (swap_total, swap_free) = (get("SwapTotal"), get("SwapFree"));
+
+        let info = SystemInfo {
+            hostname: read_trimmed("/proc/sys/kernel/hostname").unwrap_or_default(),
+            os: Os {
+                name: os_name(),
+                kernel: read_trimmed("/proc/sys/kernel/osrelease"),
+                arch: std::env::consts::ARCH.to_string(),
+            },
+            cpu_count: unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize,
+            cpu_usage: prev.and_then(|prev| cpu.usage_since(prev)),
+            load_average,
+            memory: Memory {
+                total,
+                available,
+                used: total.saturating_sub(available),
+                swap_total,
+                swap_used: swap_total.saturating_sub(swap_free),
+            },
+            uptime: uptime()? as u64,
+            boot_time: boot_time()?,
+        };
+
+        Ok((info, cpu))
+    }
+
+    pub fn cpu_times() -> Result<CpuTimes> {
+        let stat = fs::read_to_string("/proc/stat")?;
+
+        let fields: Vec<u64> = stat
+            .lines()
+            .find_map(|it| it.strip_prefix("cpu "))
+            .ok_or_else(|| Error::new("no cpu line in /proc/stat"))?
+            .split_whitespace()
+            .map(|it| it.parse().unwrap_or_default())
+            .collect();
+
+        // user nice system idle iowait irq softirq steal, guest time is already in user
+        let total = fields.iter().take(8).sum();
+        let idle =
+            fields.get(3).copied().unwrap_or_default() + fields.get(4).copied().unwrap_or_default();
+
+        Ok(CpuTimes {
+            busy: total - idle,
+            total,
+        })
+    }
+
+    pub fn clock_ticks() -> u64 {
+        unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64
+    }
+
+    /// Seconds since boot.
+    pub fn uptime() -> Result<f64> {
+        fs::read_to_string("/proc/uptime")?
+            .split_whitespace()
+            .next()
+            .and_then(|it| it.parse().ok())
+            .ok_or_else(|| Error::new("invalid /proc/uptime"))
+    }
+
+    fn boot_time() -> Result<u64> {
+        fs::read_to_string("/proc/stat")?
+            .lines()
+            .find_map(|it| it.strip_prefix("btime "))
+            .and_then(|it| it.trim().parse::<u64>().ok())
+            .map(|it| it * 1000)
+            .ok_or_else(|| Error::new("no btime in /proc/stat"))
+    }
+
+    /// Values in bytes, keyed by their names in `/proc/meminfo`.
+    fn meminfo() -> Result<HashMap<String, u64>> {
+        let meminfo = fs::read_to_string("/proc/meminfo")?;
+
+        Ok(meminfo
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                let kb: u64 = value.trim().trim_end_matches(" kB").parse().ok()?;
+
+                Some((key.to_string(), kb * 1024))
+            })
+            .collect())
+    }
+
+    fn os_name() -> Option<String> {
+        let release = fs::read_to_string("/etc/os-release")
+            .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+            .ok()?;
+
+        release.lines().find_map(|it| {
+            let value = it.strip_prefix("PRETTY_NAME=")?;
+            Some(value.trim_matches('"').to_string())
+        })
+    }
+
+    fn read_trimmed(path: &str) -> Option<String> {
+        fs::read_to_string(path)
+            .ok()
+            .map(|it| it.trim().to_string())
+    }
+}
@@ -0,0 +1,438 @@
+use crate::error::{Error, Result};
+use crate::jobs::now;
+use crate::process::{Env, Signal, Status, exit_signal, send_signal, stop_all};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::task::spawn_blocking;
+use tokio::time::timeout;
+
+/// Output kept for clients that connect later, so a reload redraws the screen.
+const SCROLLBACK_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Info {
+    pub id: u64,
+    pub pid: Option<u32>,
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub rows: u16,
+    pub cols: u16,
+    pub status: Status,
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub started_time: u64,
+    pub finished_time: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnOptions {
+    /// The user's shell when not set.
+    pub cmd: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub env: Option<Env>,
+    #[serde(default = "default_rows")]
+    pub rows: u16,
+    #[serde(default = "default_cols")]
+    pub cols: u16,
+}
+
+fn default_rows() -> u16 {
+    24
+}
+
+fn default_cols() -> u16 {
+    80
+}
+
+#[derive(Debug, Clone)]
+pub enum Chunk {
+    Data(Bytes),
+    Exit {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+#[derive(Debug)]
+struct Session {
+    info: watch::Sender<Info>,
+    pty: Pty,
+    scrollback: Mutex<VecDeque<u8>>,
+    chunks: broadcast::Sender<Chunk>,
+}
+
+/// Shells running on a pseudo-terminal, driven by a terminal emulator in the client.
+#[derive(Debug, Clone, Default)]
+pub struct Terminals {
+    entries: Arc<RwLock<BTreeMap<u64, Arc<Session>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Terminals {
+    pub fn spawn(&self, options: &SpawnOptions) -> Result<Info> {
+        let cmd = match &options.cmd {
+            Some(cmd) => cmd.clone(),
+            None => default_shell(),
+        };
+
+        let (pty, mut child) = Pty::spawn(&cmd, options)?;
+        let reader = pty.reader()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let info = Info {
+            id,
+            pid: child.id(),
+            cmd,
+            args: options.args.clone(),
+            rows: options.rows,
+            cols: options.cols,
+            status: Status::Running,
+            code: None,
+            signal: None,
+            started_time: now(),
+            finished_time: None,
+        };
+
+        let session = Arc::new(Session {
+            info: watch::Sender::new(info.clone()),
+            pty,
+            scrollback: Mutex::new(VecDeque::new()),
+            chunks: broadcast::channel(1024).0,
+        });
+
+        self.entries.write()?.insert(id, session.clone());
+
+        let output = {
+            let session = session.clone();
+            tokio::task::spawn_blocking(move || read_output(reader, &session))
+        };
+
+        tokio::spawn(async move {
+            let status = child.wait().await;
+
+            // whatever the shell wrote last goes out before the exit
+            timeout(Duration::from_secs(1), output).await.ok();
+
+            let (code, signal) = match &status {
+                Ok(status) => (status.code(), exit_signal(status)),
+                Err(_) => (None, None),
+            };
+
+            // status first, so a client subscribing in between still sees the exit
+            session.info.send_modify(|info| {
+                info.status = Status::Exited;
+                info.code = code;
+                info.signal = signal;
+                info.finished_time = Some(now());
+            });
+            session.chunks.send(Chunk::Exit { code, signal }).ok();
+        });
+
+        Ok(info)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Info> {
+        Ok(self.session(id)?.info.borrow().clone())
+    }
+
+    pub fn list(&self) -> Result<Vec<Info>> {
+        Ok(self
+            .entries
+            .read()?
+            .values()
+            .map(|it| it.info.borrow().clone())
+            .collect())
+    }
+
+    /// Writes to the terminal. A shell that isn't reading blocks the write once
+    /// the pty buffer is full, so it happens off the async workers.
+    pub async fn input(&self, id: u64, data: Vec<u8>) -> Result<()> {
+        let session = self.session(id)?;
+
+        if session.info.borrow().status == Status::Exited {
+            return Err(Error::new(format!("terminal has exited: {id}")));
+        }
+
+        spawn_blocking(move || session.pty.write(&data)).await?
+    }
+
+    pub fn resize(&self, id: u64, rows: u16, cols: u16) -> Result<Info> {
+        let session = self.session(id)?;
+        session.pty.resize(rows, cols)?;
+
+        session.info.send_modify(|info| {
+            info.rows = rows;
+            info.cols = cols;
+        });
+
+        Ok(session.info.borrow().clone())
+    }
+
+    pub fn kill(&self, id: u64, signal: Signal) -> Result<Info> {
+        let info = self.get(id)?;
+
+        if info.status == Status::Running
+            && let Some(pid) = info.pid
+        {
+            send_signal(pid, signal)?;
+        }
+
+        Ok(info)
+    }
+
+    /// Resolves once the shell has exited, or with it still running when `wait` runs out.
+    pub async fn wait(&self, id: u64, wait: Option<Duration>) -> Result<Info> {
+        let session = self.session(id)?;
+        let mut info = session.info.subscribe();
+
+        let exited = async {
+            info.wait_for(|it| it.status == Status::Exited)
+                .await
+                .map(|it| it.clone())
+                .map_err(|_| Error::new(format!("terminal is gone: {id}")))
+        };
+
+        match wait {
+            Some(wait) => match timeout(wait, exited).await {
+                Ok(res) => res,
+                Err(_) => Ok(session.info.borrow().clone()),
+            },
+            None => exited.await,
+        }
+    }
+
+    pub fn remove(&self, id: u64) -> Result<Info> {
+        let info = self.get(id)?;
+
+        if info.status == Status::Running {
+            return Err(Error::new(format!("terminal is running: {id}")));
+        }
+
+        self.entries.write()?.remove(&id);
+        Ok(info)
+    }
+
+    /// The scrollback so far, followed by everything written from now on.
+    pub fn subscribe(&self, id: u64) -> Result<(Bytes, Option<Chunk>, broadcast::Receiver<Chunk>)> {
+        let session = self.session(id)?;
+        let scrollback = session.scrollback.lock()?;
+
+        // taken under the lock so nothing falls between the two
+        let receiver = session.chunks.subscribe();
+        let history = Bytes::from(scrollback.iter().copied().collect::<Vec<u8>>());
+
+        let info = session.info.borrow();
+        let exit = (info.status == Status::Exited).then_some(Chunk::Exit {
+            code: info.code,
+            signal: info.signal,
+        });
+
+        Ok((history, exit, receiver))
+    }
+
+    /// Hangs up on every running shell and kills the ones that don't exit in time.
+    pub async fn shutdown(&self) {
+        let running: Vec<u64> = self
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|it| it.status == Status::Running)
+            .map(|it| it.id)
+            .collect();
+
+        stop_all(
+            &running,
+            Signal::Hup,
+            |id, signal| self.kill(id, signal),
+            |id, grace| async move { Ok(self.wait(id, Some(grace)).await?.status) },
+        )
+        .await;
+    }
+
+    fn session(&self, id: u64) -> Result<Arc<Session>> {
+        self.entries
+            .read()?
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::new(format!("terminal not found: {id}")))
+    }
+}
+
+fn read_output(mut reader: impl std::io::Read, session: &Session) {
+    let mut buf = vec![0u8; 16 * 1024];
+
+    // the read fails with EIO on linux once the shell side has closed
+    while let Ok(read) = reader.read(&mut buf)
+        && read > 0
+    {
+        let data = Bytes::copy_from_slice(&buf[..read]);
+
+        if let Ok(mut scrollback) = session.scrollback.lock() {
+            scrollback.extend(data.iter());
+
+            let extra = scrollback.len().saturating_sub(SCROLLBACK_LEN);
+            scrollback.drain(..extra);
+
+            session.chunks.send(Chunk::Data(data)).ok();
+        }
+    }
+}
+
+fn default_shell() -> String {
+    #[cfg(unix)]
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+
+    #[cfg(not(unix))]
+    let shell = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+
+    shell
+}
+
+#[cfg(unix)]
+#[derive(Debug)]
+struct Pty {
+    master: std::os::fd::OwnedFd,
+    writer: Mutex<std::fs::File>,
+}
+
+#[cfg(unix)]
+impl Pty {
+    fn spawn(cmd: &str, options: &SpawnOptions) -> Result<(Self, tokio::process::Child)> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::process::Stdio;
+
+        let (mut master, mut slave) = (0, 0);
+        let size = winsize(options.rows, options.cols);
+
+        let res = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            )
+        };
+
+        if res != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+        // neither end should leak into other children
+        for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+
+        let mut command = tokio::process::Command::new(cmd);
+
+        command
+            .args(&options.args)
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .kill_on_drop(true);
+
+        if let Some(cwd) = &options.cwd {
+            command.current_dir(cwd);
+        }
+
+        if let Some(env) = &options.env {
+            env.apply(&mut command);
+        }
+
+        // a new session with the terminal as its controlling one, that's
+        // what gives the shell job control and ctrl-c
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        // the slave copies go away with the command
+        let child = command.spawn()?;
+        let writer = std::fs::File::from(master.try_clone()?);
+
+        Ok((
+            Self {
+                master,
+                writer: Mutex::new(writer),
+            },
+            child,
+        ))
+    }
+
+    fn reader(&self) -> Result<std::fs::File> {
+        Ok(std::fs::File::from(self.master.try_clone()?))
+    }
+
+    fn write(&self, data: &[u8]) -> Result<()> {
+        let mut writer = self.writer.lock()?;
+        writer.write_all(data)?;
+
+        Ok(())
+    }
+
+    fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let size = winsize(rows, cols);
+
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) } == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn winsize(rows: u16, cols: u16) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows.max(1),
+        ws_col: cols.max(1),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+#[cfg(not(unix))]
+#[derive(Debug)]
+struct Pty;
+
+#[cfg(not(unix))]
+impl Pty {
+    fn spawn(_: &str, _: &SpawnOptions) -> Result<(Self, tokio::process::Child)> {
+        Err(Error::new("terminals are only supported on unix"))
+    }
+
+    fn reader(&self) -> Result<std::io::Empty> {
+        Ok(std::io::empty())
+    }
+
+    fn write(&self, _: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, _: u16, _: u16) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::{Error, Result};
+use crate::fs::{WriteOptions, write_file};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Bigger sizes are refused, a thumbnail that large defeats the purpose.
+const MAX_DIMENSION: u32 = 4096;
+
+/// Sources bigger than this aren't read into memory to be decoded.
+pub const MAX_SOURCE_LEN: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fit {
+    /// Scales down to fit inside the box, keeping the aspect ratio.
+    #[default]
+    Contain,
+    /// Scales to cover the box and crops what's outside of it.
+    Cover,
+    /// Stretches to the exact size.
+    Fill,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Params {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    #[serde(default)]
+    pub fit: Fit,
+    pub format: Option<Format>,
+}
+
+impl Params {
+    pub fn is_empty(&self) -> bool {
+        self.w.is_none() && self.h.is_none() && self.format.is_none()
+    }
+}
+
+/// Returns the cached thumbnail for `key`, generating it from the bytes given
+/// by `read` on a miss. The key should change whenever the source does. The
+/// oldest thumbnails are removed once the cache grows past `max_bytes`.
+pub fn thumbnail(
+    cache_dir: &Path,
+    max_bytes: u64,
+    key: &str,
+    params: &Params,
+    read: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<PathBuf> {
+    for size in [params.w, params.h].into_iter().flatten() {
+        if size == 0 || size > MAX_DIMENSION {
+            return Err(Error::new(format!(
+                "thumbnail size must be between 1 and {MAX_DIMENSION}"
+            )));
+        }
+    }
+
+    let hash = Sha256::digest(format!(
+        "{key}|{:?}|{:?}|{:?}|{:?}",
+        params.w, params.h, params.fit, params.format
+    ));
+
+    // no extension, the type gets sniffed when the file is served
+    let path = cache_dir.join(hex::encode(hash));
+
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let image = image::load_from_memory(&read()?)
+        .map_err(|err| Error::new(format!("failed to decode image: {err}")))?;
+    let image = resize(image, params);
+
+    let format = params.format.unwrap_or(match image.color().has_alpha() {
+        true => Format::Png,
+        false => Format::Jpeg,
+    });
+
+    // jpeg has no alpha channel and the webp encoder only takes 8 bit rgb(a)
+    let (image, format) = match format {
+        Format::Jpeg => (DynamicImage::from(image.to_rgb8()), ImageFormat::Jpeg),
+        Format::Png => (image, ImageFormat::Png),
+        Format::Webp => (DynamicImage::from(image.to_rgba8()), ImageFormat::WebP),
+    };
+
+    let mut data = Cursor::new(vec![]);
+    image.write_to(&mut data, format)?;
+
+    let options = WriteOptions {
+        atomic: true,
+        create_parents: true,
+        ..Default::default()
+    };
+
+    write_file(&path, data.get_ref(), &options)?;
+    prune(cache_dir, max_bytes, &path)?;
+
+    Ok(path)
+}
+
+/// Removes the oldest thumbnails until the rest fit in `max_bytes`, `keep` is
+/// left alone since it's about to be served.
+fn prune(cache_dir: &Path, max_bytes: u64, keep: &Path) -> Result<()> {
+    let mut files = vec![];
+    let mut total = 0;
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+
+        // temp files of writes still in progress start with a dot
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+
+        if meta.is_file() {
+            total += meta.len();
+            files.push((meta.modified()?, meta.len(), entry.path()));
+        }
+    }
+
+    files.sort_by_key(|it| it.0);
+
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+
+        // another request may have pruned it already
+        if path != keep && fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+
+    Ok(())
+}
+
+fn resize(image: DynamicImage, params: &Params) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let filter = FilterType::CatmullRom;
+
+    match (params.fit, params.w, params.h) {
+        (_, None, None) => image,
+        (Fit::Fill, w, h) => image.resize_exact(w.unwrap_or(width), h.unwrap_or(height), filter),
+        (Fit::Cover, Some(w), Some(h)) => image.resize_to_fill(w, h, filter),
+        // never scaled up, a missing side doesn't constrain
+        (_, w, h) => {
+            let w = w.unwrap_or(u32::MAX);
+            let h = h.unwrap_or(u32::MAX);
+
+            if width <= w && height <= h {
+                image
+            } else {
+                image.resize(w, h, filter)
+            }
+        }
+    }
+}
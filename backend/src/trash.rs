@@ -0,0 +1,313 @@
+// https://specifications.freedesktop.org/trash-spec/latest
+
+use crate::error::{Error, Result};
+use crate::fs::{device, mount_point, mounts, unique_name};
+use axum::http::StatusCode;
+use chrono::Local;
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode, percent_encode};
+use serde::Serialize;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const INFO_EXT: &str = "trashinfo";
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItem {
+    /// Location of the entry inside the trash, used to restore or purge it.
+    pub path: PathBuf,
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+}
+
+/// Moves the entry into the home trash, or the `.Trash-$uid` directory of its
+/// mount point when it lives on another device.
+pub fn trash(path: &Path) -> Result<TrashItem> {
+    let path = std::path::absolute(path)?;
+    let meta = path.symlink_metadata()?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::new(format!("invalid path: {}", path.display())))?;
+
+    let home = home_trash()?;
+    let (trash_dir, original_path) = if device(&path)? == device(&existing_ancestor(&home))? {
+        (home, path.clone())
+    } else {
+        let top = mount_point(&path)?;
+        let relative = path.strip_prefix(&top).unwrap_or(&path).to_path_buf();
+
+        (top.join(format!(".Trash-{}", uid())), relative)
+    };
+
+    fs::create_dir_all(trash_dir.join("files"))?;
+    fs::create_dir_all(trash_dir.join("info"))?;
+
+    let deletion_date = Local::now().format(DATE_FORMAT).to_string();
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={deletion_date}\n",
+        encode_path(&original_path)
+    );
+
+    // the info file is created exclusively first to claim the name, as the spec requires
+    for n in 1.. {
+        let name = unique_name(name, n);
+        let info_path = info_path(&trash_dir, &name);
+
+        let mut info = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let files_path = trash_dir.join("files").join(&name);
+        let res = info
+            .write_all(contents.as_bytes())
+            .and_then(|_| info.sync_all())
+            .and_then(|_| fs::rename(&path, &files_path));
+
+        if let Err(err) = res {
+            let _ = fs::remove_file(&info_path);
+            return Err(err.into());
+        }
+
+        return Ok(TrashItem {
+            path: files_path,
+            original_path: path,
+            deletion_date,
+            is_dir: meta.is_dir(),
+            size: (!meta.is_dir()).then_some(meta.len()),
+        });
+    }
+
+    unreachable!()
+}
+
+pub fn list() -> Result<Vec<TrashItem>> {
+    let mut res = vec![];
+
+    for trash_dir in trash_dirs()? {
+        let Ok(entries) = fs::read_dir(trash_dir.join("info")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let info_path = entry.path();
+
+            if info_path.extension().is_some_and(|it| it == INFO_EXT)
+                && let Ok(item) = read_item(&trash_dir, &info_path)
+            {
+                res.push(item);
+            }
+        }
+    }
+
+    res.sort_by(|a, b| b.deletion_date.cmp(&a.deletion_date));
+    Ok(res)
+}
+
+/// Puts a trashed entry back at its original location, or at `dst` when given.
+pub fn restore(path: &Path, dst: Option<&Path>) -> Result<PathBuf> {
+    let (trash_dir, name) = locate(path)?;
+    let info_path = info_path(&trash_dir, &name);
+    let item = read_item(&trash_dir, &info_path)?;
+    let dst = dst.map(Path::to_path_buf).unwrap_or(item.original_path);
+
+    if dst.symlink_metadata().is_ok() {
+        return Err(Error::new(format!("already exists: {}", dst.display()))
+            .with_status(StatusCode::CONFLICT));
+    }
+
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::rename(path, &dst)?;
+    fs::remove_file(info_path)?;
+
+    Ok(dst)
+}
+
+/// Permanently deletes the given trashed entries, or everything in the trash.
+pub fn empty(paths: Option<Vec<PathBuf>>) -> Result<u64> {
+    let paths = match paths {
+        Some(paths) => paths,
+        None => list()?.into_iter().map(|it| it.path).collect(),
+    };
+
+    let mut removed = 0;
+
+    for path in paths {
+        let (trash_dir, name) = locate(&path)?;
+
+        if path.symlink_metadata().is_ok_and(|it| it.is_dir()) {
+            fs::remove_dir_all(&path)?;
+        } else if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path)?;
+        }
+
+        // the info file goes last so a failed purge can still be listed
+        fs::remove_file(info_path(&trash_dir, &name))?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+fn read_item(trash_dir: &Path, info_path: &Path) -> Result<TrashItem> {
+    let contents = fs::read_to_string(info_path)?;
+    let mut original_path = None;
+    let mut deletion_date = String::new();
+
+    for line in contents.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            original_path = Some(decode_path(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deletion_date = value.to_string();
+        }
+    }
+
+    let original_path = original_path
+        .ok_or_else(|| Error::new(format!("invalid trash info: {}", info_path.display())))?;
+
+    // relative paths are only used by the per mount point trash directories
+    let original_path = match (original_path.is_relative(), trash_dir.parent()) {
+        (true, Some(top)) => top.join(original_path),
+        _ => original_path,
+    };
+
+    let name = info_path.file_stem().unwrap_or_default();
+    let path = trash_dir.join("files").join(name);
+    let meta = path.symlink_metadata().ok();
+
+    Ok(TrashItem {
+        path,
+        original_path,
+        deletion_date,
+        is_dir: meta.as_ref().is_some_and(|it| it.is_dir()),
+        size: meta.filter(|it| !it.is_dir()).map(|it| it.len()),
+    })
+}
+
+fn locate(path: &Path) -> Result<(PathBuf, OsString)> {
+    let invalid = || Error::new(format!("not in trash: {}", path.display()));
+
+    let files_dir = path.parent().ok_or_else(invalid)?;
+    let name = path.file_name().ok_or_else(invalid)?.to_os_string();
+
+    if files_dir.file_name().is_none_or(|it| it != "files") {
+        return Err(invalid());
+    }
+
+    let trash_dir = files_dir.parent().ok_or_else(invalid)?.to_path_buf();
+
+    if !trash_dirs()?.contains(&trash_dir) {
+        return Err(invalid());
+    }
+
+    Ok((trash_dir, name))
+}
+
+fn trash_dirs() -> Result<Vec<PathBuf>> {
+    let mut res = vec![home_trash()?];
+    let name = format!(".Trash-{}", uid());
+
+    for mount in mounts() {
+        let dir = mount.path.join(&name);
+
+        if dir.is_dir() && !res.contains(&dir) {
+            res.push(dir);
+        }
+    }
+
+    Ok(res)
+}
+
+fn home_trash() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|it| !it.is_empty()) {
+        return Ok(PathBuf::from(dir).join("Trash"));
+    }
+
+    let home = env::home_dir().ok_or_else(|| Error::new("home directory not found"))?;
+    Ok(home.join(".local").join("share").join("Trash"))
+}
+
+fn info_path(trash_dir: &Path, name: &std::ffi::OsStr) -> PathBuf {
+    let mut file_name = name.to_os_string();
+    file_name.push(".");
+    file_name.push(INFO_EXT);
+
+    trash_dir.join("info").join(file_name)
+}
+
+fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|it| it.exists())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// Percent-encodes a path the way `file://` uris and trash info files want it.
+#[cfg(unix)]
+pub fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    percent_encode(path.as_os_str().as_bytes(), PATH_ENCODE_SET).to_string()
+}
+
+#[cfg(not(unix))]
+pub fn encode_path(path: &Path) -> String {
+    percent_encode(path.to_string_lossy().as_bytes(), PATH_ENCODE_SET).to_string()
+}
+
+#[cfg(unix)]
+fn decode_path(value: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(
+        percent_decode(value.as_bytes()).collect(),
+    ))
+}
+
+#[cfg(not(unix))]
+fn decode_path(value: &str) -> PathBuf {
+    PathBuf::from(
+        percent_decode(value.as_bytes())
+            .decode_utf8_lossy()
+            .to_string(),
+    )
+}
+
+#[cfg(unix)]
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn uid() -> u32 {
+    0
+}
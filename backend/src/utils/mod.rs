@@ -1,19 +1,37 @@
+use crate::archive::cache::Cache as ArchiveCache;
 use crate::config::Config;
 use crate::db::mongo::Client as MongoClient;
 use crate::db::sqlite::Client as SqliteClient;
+use crate::download::queue::Queue as DownloadQueue;
 use crate::error::{Error, Result};
+use crate::jobs::Jobs;
+use crate::process::Processes;
+use crate::schedule::Scheduler;
+use crate::terminal::Terminals;
 use chrono::{DateTime, Local, Utc};
 use reqwest::Client as HttpClient;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
 use std::sync::{Arc, RwLock};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone)]
 pub struct State {
     pub http_client: HttpClient,
     pub config: Arc<RwLock<Config>>,
     pub store: Arc<RwLock<BTreeMap<String, JsonValue>>>,
+    pub jobs: Jobs,
+    pub archives: ArchiveCache,
+    pub downloads: DownloadQueue,
+    pub processes: Processes,
+    pub scheduler: Scheduler,
+    pub terminals: Terminals,
+    /// Cancelled once the server is shutting down, endless streams stop on it.
+    pub shutdown: CancellationToken,
     pub mongo_client: Option<MongoClient>,
     pub sqlite_client: Option<SqliteClient>,
 }
@@ -58,3 +76,105 @@ impl std::str::FromStr for Timestamp {
         ))
     }
 }
+
+/// Compares strings the way people expect, so `page2` sorts before `page10`.
+/// Letters compare case-insensitively and digit runs by their numeric value.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        let ord = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let (tx, ty) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+
+                // fewer leading zeros first when the values are equal
+                tx.len()
+                    .cmp(&ty.len())
+                    .then_with(|| tx.cmp(ty))
+                    .then_with(|| x.len().cmp(&y.len()))
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                a.next();
+                b.next();
+                ord
+            }
+        };
+
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut res = String::new();
+
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        res.push(c);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|it| it.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn natural_cmp_orders_digit_runs_by_value() {
+        assert_eq!(natural_cmp("a2", "a10"), Ordering::Less);
+        assert_eq!(natural_cmp("a10", "a2"), Ordering::Greater);
+        assert_eq!(natural_cmp("page9b", "page10a"), Ordering::Less);
+        assert_eq!(sorted(&["a10", "a1", "a2"]), ["a1", "a2", "a10"]);
+    }
+
+    #[test]
+    fn natural_cmp_puts_fewer_leading_zeros_first() {
+        assert_eq!(natural_cmp("a007", "a7"), Ordering::Greater);
+        assert_eq!(natural_cmp("a007", "a8"), Ordering::Less);
+        assert_eq!(natural_cmp("a0", "a00"), Ordering::Less);
+        assert_eq!(sorted(&["x01", "x1", "x001"]), ["x1", "x01", "x001"]);
+    }
+
+    #[test]
+    fn natural_cmp_handles_digit_runs_longer_than_u64() {
+        let big = "99999999999999999999999999";
+        let bigger = "100000000000000000000000000";
+
+        assert_eq!(natural_cmp(big, bigger), Ordering::Less);
+        assert_eq!(
+            natural_cmp(&format!("f{big}"), &format!("f{big}")),
+            Ordering::Equal
+        );
+        assert_eq!(natural_cmp(&format!("0{big}"), bigger), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case() {
+        assert_eq!(natural_cmp("Apple", "apple"), Ordering::Equal);
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("B2", "b10"), Ordering::Less);
+    }
+
+    #[test]
+    fn take_digits_stops_at_the_first_non_digit() {
+        let mut chars = "0123abc4".chars().peekable();
+
+        assert_eq!(take_digits(&mut chars), "0123");
+        assert_eq!(chars.collect::<String>(), "abc4");
+        assert_eq!(take_digits(&mut "abc".chars().peekable()), "");
+    }
+}
@@ -1,5 +1,7 @@
 import { getBridgeClientRequestor } from '@/lib/utils'
 import type { Dictionary } from '@/lib'
+import type { Preset } from '@/lib/process'
+import type { Task } from '@/lib/schedule'
 
 export type Action = 'get'
 
@@ -19,4 +21,21 @@ export type Config = {
   }
   theme: { fontSize: string }
   vars: Dictionary<Dictionary<string>>
+  cache?: {
+    dir?: string
+    archives?: number
+    archiveBytes?: number
+    spoolBytes?: number
+    thumbnailBytes?: number
+  }
+  commands?: { presetsOnly?: boolean; presets?: Dictionary<Preset> }
+  downloads?: {
+    state?: string
+    concurrency?: number
+    perHost?: number
+    rate?: number
+    retries?: number
+  }
+  jobs?: { retention?: number }
+  schedule?: { state?: string; history?: number; tasks?: Dictionary<Task> }
 }
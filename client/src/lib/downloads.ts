@@ -0,0 +1,59 @@
+import { getBridgeClientRequestor } from '@/lib/utils'
+import type { Nullable } from '@/lib'
+import type { DownloadOptions } from '@/lib/fs'
+import type { Progress } from '@/lib/jobs'
+
+export type Status = 'queued' | 'running' | 'paused' | 'completed' | 'failed' | 'cancelled'
+
+export type Item = {
+  id: number
+  url: string
+  path: string
+  options: DownloadOptions
+  priority: number
+  status: Status
+  attempts: number
+  error: Nullable<string>
+  addedTime: number
+  retryTime: Nullable<number>
+  finishedTime: Nullable<number>
+  jobId: Nullable<number>
+  progress: Nullable<Progress>
+}
+
+export type Enqueue = { url: string; path: string; options?: DownloadOptions; priority?: number }
+
+export type Action = 'enqueue' | 'list' | 'pause' | 'resume' | 'cancel' | 'set-priority' | 'clear'
+
+export class Client {
+  private request = getBridgeClientRequestor<Action>('downloads')
+
+  async enqueue(items: Enqueue[]): Promise<Item[]> {
+    return await this.request({ action: 'enqueue', data: { items } })
+  }
+
+  async list(): Promise<Item[]> {
+    return await this.request({ action: 'list', data: {} })
+  }
+
+  async pause(id: number): Promise<Item> {
+    return await this.request({ action: 'pause', data: { id } })
+  }
+
+  async resume(id: number): Promise<Item> {
+    return await this.request({ action: 'resume', data: { id } })
+  }
+
+  async cancel(id: number): Promise<Item> {
+    return await this.request({ action: 'cancel', data: { id } })
+  }
+
+  async setPriority(id: number, priority: number): Promise<Item> {
+    return await this.request({ action: 'set-priority', data: { id, priority } })
+  }
+
+  // only finished items are removed
+  async clear(ids?: number[]): Promise<Item[]> {
+    return await this.request({ action: 'clear', data: { ids } })
+  }
+}
@@ -1,33 +1,178 @@
 import { getBridgeClientRequestor } from '@/lib/utils'
+import type { Nullable } from '@/lib'
+import type { Job } from '@/lib/jobs'
+
+export type Category = 'image' | 'video' | 'audio' | 'text' | 'archive' | 'document' | 'other'
 
 export type Stats = {
   size: number
   isDir: boolean
   isFile: boolean
+  isSymlink: boolean
+  symlinkTarget: Nullable<string>
   modifiedTime: number
+  accessedTime: Nullable<number>
+  createdTime: Nullable<number>
+  readonly: boolean
+  mode: Nullable<number>
+  uid: Nullable<number>
+  gid: Nullable<number>
+  user: Nullable<string>
+  group: Nullable<string>
+  inode: Nullable<number>
+  nlink: Nullable<number>
+  mime: Nullable<string>
+  category: Nullable<Category>
+}
+
+export type DirEntry = { name: string; stats: Nullable<Stats> }
+
+export type WriteMode = 'overwrite' | 'append' | 'create-new'
+
+export type WriteOptions = {
+  mode?: WriteMode
+  atomic?: boolean
+  createParents?: boolean
+  ifModifiedTime?: number
+}
+
+export type CopyOptions = {
+  overwrite?: boolean
+  preserveTimes?: boolean
+  preservePermissions?: boolean
+}
+
+export type TransferFailure = { path: string; message: string }
+
+export type TransferReport = { files: number; bytes: number; failures: TransferFailure[] }
+
+export type TrashItem = {
+  path: string
+  originalPath: string
+  deletionDate: string
+  isDir: boolean
+  size: Nullable<number>
+}
+
+export type DiskUsage = {
+  path: string
+  size: number
+  allocatedSize: number
+  files: number
+  dirs: number
+  children: DiskUsage[]
+}
+
+export type DiskInfo = {
+  mountPoint: string
+  source: Nullable<string>
+  fsType: Nullable<string>
+  totalBytes: number
+  freeBytes: number
+  availableBytes: number
 }
 
+export type HashAlgorithm = 'sha256' | 'sha1' | 'md5' | 'blake3' | 'crc32'
+
+export type DownloadOptions = {
+  checksum?: { algorithm?: HashAlgorithm; value: string }
+  size?: number
+  overwrite?: boolean
+}
+
+export type DownloadReport = {
+  path: string
+  size: number
+  resumedFrom: number
+  checksum: Nullable<string>
+}
+
+export type DuplicateGroup = { hash: string; size: number; paths: string[] }
+
+export type Duplicates = { groups: DuplicateGroup[]; reclaimableBytes: number }
+
+export type ArchiveEntry = {
+  name: string
+  size: number
+  compressedSize: Nullable<number>
+  isDir: boolean
+  modifiedTime: Nullable<number>
+  mode: Nullable<number>
+  compression: Nullable<string>
+  crc: Nullable<number>
+  encrypted: boolean
+  mime: Nullable<string>
+  category: Nullable<Category>
+}
+
+export type ArchiveSort = 'natural' | 'name' | 'size' | 'modified'
+
+export type ArchiveListOptions = {
+  sort?: ArchiveSort
+  glob?: string
+  category?: Category
+  skipDirs?: boolean
+}
+
+export type ExtractConflict = 'error' | 'skip' | 'overwrite' | 'rename'
+
+export type ExtractOptions = { entries?: string[]; onConflict?: ExtractConflict }
+
+export type ArchiveFormat = 'zip' | 'tar-gz'
+
+export type CreateArchiveOptions = { format?: ArchiveFormat; level?: number; overwrite?: boolean }
+
 export type Action =
   | 'stats'
+  | 'chmod'
+  | 'set-times'
+  | 'symlink'
   | 'read-dir'
   | 'create-dir'
   | 'rename'
   | 'remove'
+  | 'trash'
+  | 'list-trash'
+  | 'restore'
+  | 'empty-trash'
   | 'copy-file'
+  | 'copy'
+  | 'move'
   | 'read-text-file'
   | 'write-text-file'
   | 'download-file'
+  | 'du'
+  | 'disk-info'
+  | 'hash'
+  | 'find-duplicates'
   | 'read-archive'
+  | 'extract'
+  | 'create-archive'
 
 export class Client {
   private request = getBridgeClientRequestor<Action>('fs')
 
-  async stats(path: string): Promise<Stats> {
-    return await this.request({ action: 'stats', data: { path } })
+  // the mime type comes from the extension unless `sniff` reads the file's head
+  async stats(path: string, sniff?: boolean): Promise<Stats> {
+    return await this.request({ action: 'stats', data: { path, sniff } })
+  }
+
+  async chmod(path: string, mode: number | string): Promise<Stats> {
+    return await this.request({ action: 'chmod', data: { path, mode } })
+  }
+
+  async setTimes(path: string, modifiedTime?: number, accessedTime?: number): Promise<Stats> {
+    return await this.request({ action: 'set-times', data: { path, modifiedTime, accessedTime } })
+  }
+
+  async symlink(target: string, path: string): Promise<Stats> {
+    return await this.request({ action: 'symlink', data: { target, path } })
   }
 
-  async readDir(path: string): Promise<string[]> {
-    return await this.request({ action: 'read-dir', data: { path } })
+  async readDir(path: string): Promise<string[]>
+  async readDir(path: string, detailed: true): Promise<DirEntry[]>
+  async readDir(path: string, detailed = false): Promise<string[] | DirEntry[]> {
+    return await this.request({ action: 'read-dir', data: { path, detailed } })
   }
 
   async createDir(path: string, recursive?: boolean): Promise<void> {
@@ -42,24 +187,108 @@ export class Client {
     return await this.request({ action: 'remove', data: { path, recursive } })
   }
 
+  async trash(path: string): Promise<TrashItem> {
+    return await this.request({ action: 'trash', data: { path } })
+  }
+
+  async listTrash(): Promise<TrashItem[]> {
+    return await this.request({ action: 'list-trash', data: {} })
+  }
+
+  async restore(path: string, dst?: string): Promise<string> {
+    return await this.request({ action: 'restore', data: { path, dst } })
+  }
+
+  async emptyTrash(paths?: string[]): Promise<number> {
+    return await this.request({ action: 'empty-trash', data: { paths } })
+  }
+
   async copyFile(src: string, dst: string): Promise<void> {
     return await this.request({ action: 'copy-file', data: { src, dst } })
   }
 
+  async copy(src: string, dst: string, options: CopyOptions = {}): Promise<Job<TransferReport>> {
+    return await this.request({ action: 'copy', data: { src, dst, options } })
+  }
+
+  async move(src: string, dst: string, options: CopyOptions = {}): Promise<Job<TransferReport>> {
+    return await this.request({ action: 'move', data: { src, dst, options } })
+  }
+
   async readTextFile(path: string): Promise<string> {
     return await this.request({ action: 'read-text-file', data: { path } })
   }
 
-  async writeTextFile(path: string, data: string): Promise<void> {
-    return await this.request({ action: 'write-text-file', data: { path, data } })
+  async writeTextFile(path: string, data: string, options: WriteOptions = {}): Promise<Stats> {
+    return await this.request({ action: 'write-text-file', data: { path, data, options } })
+  }
+
+  async downloadFile(
+    url: string,
+    path: string,
+    options?: DownloadOptions,
+  ): Promise<Job<DownloadReport>> {
+    return await this.request({ action: 'download-file', data: { url, path, options } })
+  }
+
+  async du(path: string, depth?: number): Promise<Job<DiskUsage>> {
+    return await this.request({ action: 'du', data: { path, depth } })
+  }
+
+  async diskInfo(path: string): Promise<DiskInfo> {
+    return await this.request({ action: 'disk-info', data: { path } })
+  }
+
+  async hash(path: string, algorithm?: HashAlgorithm): Promise<string> {
+    return await this.request({ action: 'hash', data: { path, algorithm } })
+  }
+
+  async findDuplicates(
+    path: string,
+    algorithm?: HashAlgorithm,
+    minSize?: number,
+  ): Promise<Job<Duplicates>> {
+    return await this.request({ action: 'find-duplicates', data: { path, algorithm, minSize } })
+  }
+
+  async readArchive(
+    path: string,
+    container?: string,
+    options?: ArchiveListOptions,
+  ): Promise<string[]>
+  async readArchive(
+    path: string,
+    container: string | undefined,
+    options: ArchiveListOptions | undefined,
+    detailed: true,
+  ): Promise<ArchiveEntry[]>
+  async readArchive(
+    path: string,
+    container?: string,
+    options?: ArchiveListOptions,
+    detailed = false,
+  ): Promise<string[] | ArchiveEntry[]> {
+    return await this.request({
+      action: 'read-archive',
+      data: { path, container, options, detailed },
+    })
   }
 
-  async downloadFile(url: string, path: string): Promise<void> {
-    return await this.request({ action: 'download-file', data: { url, path } })
+  async extract(
+    path: string,
+    dst: string,
+    options?: ExtractOptions,
+    container?: string,
+  ): Promise<Job<TransferReport>> {
+    return await this.request({ action: 'extract', data: { path, dst, options, container } })
   }
 
-  async readArchive(path: string, container: string): Promise<string[]> {
-    return await this.request({ action: 'read-archive', data: { path, container } })
+  async createArchive(
+    paths: string[],
+    dst: string,
+    options?: CreateArchiveOptions,
+  ): Promise<Job<TransferReport>> {
+    return await this.request({ action: 'create-archive', data: { paths, dst, options } })
   }
 }
 
@@ -0,0 +1,68 @@
+import { getBridgeClientRequestor } from '@/lib/utils'
+import { stringifyQuery } from '@/lib/urls'
+import type { Nullable } from '@/lib'
+
+export type Status = 'running' | 'completed' | 'failed' | 'cancelled'
+
+export type Progress = {
+  done: number
+  total: Nullable<number>
+  message: Nullable<string>
+  speed: Nullable<number>
+}
+
+export type Job<T = unknown> = {
+  id: number
+  kind: string
+  status: Status
+  progress: Progress
+  result: Nullable<T>
+  error: Nullable<string>
+  startedTime: number
+  finishedTime: Nullable<number>
+}
+
+export type Kind =
+  | 'fs/copy'
+  | 'fs/move'
+  | 'fs/download'
+  | 'fs/du'
+  | 'fs/hash'
+  | 'fs/find-duplicates'
+  | 'archive/extract'
+  | 'archive/create'
+  | 'db/sqlite/execute'
+  | 'db/sqlite/fetch'
+
+export type Action = 'start' | 'status' | 'list' | 'cancel' | 'wait'
+
+export class Client {
+  private request = getBridgeClientRequestor<Action>('jobs')
+
+  // data takes the same fields as the bridge action the kind mirrors
+  async start<T>(kind: Kind, data: unknown): Promise<Job<T>> {
+    return await this.request({ action: 'start', data: { kind, data } })
+  }
+
+  async status<T>(id: number): Promise<Job<T>> {
+    return await this.request({ action: 'status', data: { id } })
+  }
+
+  async list(): Promise<Job[]> {
+    return await this.request({ action: 'list', data: {} })
+  }
+
+  async cancel(id: number): Promise<Job> {
+    return await this.request({ action: 'cancel', data: { id } })
+  }
+
+  // resolves with the job as it is when the timeout runs out
+  async wait<T>(id: number, timeoutMs?: number): Promise<Job<T>> {
+    return await this.request({ action: 'wait', data: { id, timeoutMs } })
+  }
+
+  // `job` events for every job, or only the given one until it finishes
+  events(id?: number): EventSource {
+    return new EventSource('/api/bridges/jobs/events' + stringifyQuery({ id }))
+  }
+}
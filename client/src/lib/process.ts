@@ -1,11 +1,118 @@
 import { getBridgeClientRequestor } from '@/lib/utils'
-import type { Nullable } from '@/lib'
+import { stringifyQuery } from '@/lib/urls'
+import type { Dictionary, Nullable } from '@/lib'
 
-export type Options = { cwd?: string }
+export type Env = { clear?: boolean; set?: Record<string, string>; unset?: string[] }
 
-export type Output = { stdout: string; stderr: string; status: Nullable<number> }
+export type Options = { cwd?: string; env?: Env }
 
-export type Action = 'open' | 'output'
+export type OutputOptions = Options & {
+  stdin?: string
+  timeoutMs?: number
+  maxOutputBytes?: number
+  base64?: boolean
+}
+
+export type Output = {
+  stdout: string
+  stderr: string
+  status: Nullable<number>
+  signal: Nullable<number>
+  stdoutBase64: Nullable<string>
+  stderrBase64: Nullable<string>
+  truncated: boolean
+  timedOut: boolean
+  // something the process started kept its output open, what was read until then is returned
+  incomplete: boolean
+}
+
+export type ParamType = 'string' | 'integer' | 'number' | 'boolean' | 'path'
+
+export type Param = {
+  type: ParamType
+  description?: Nullable<string>
+  default?: unknown
+  optional?: boolean
+  choices?: unknown[]
+  // string values starting with `-` are refused unless set
+  allowDash?: boolean
+}
+
+// `args` reference params as `{name}`
+export type Preset = {
+  program: string
+  description?: Nullable<string>
+  args?: string[]
+  params?: Dictionary<Param>
+  cwd?: Nullable<string>
+  env?: Nullable<Env>
+  timeoutMs?: Nullable<number>
+  maxOutputBytes?: Nullable<number>
+}
+
+export type RunPresetOptions = { stdin?: string; base64?: boolean }
+
+export type Signal = 'TERM' | 'KILL' | 'INT' | 'HUP' | 'QUIT' | 'USR1' | 'USR2'
+
+export type Status = 'running' | 'exited'
+
+export type Info = {
+  id: number
+  pid: Nullable<number>
+  cmd: string
+  args: string[]
+  status: Status
+  code: Nullable<number>
+  signal: Nullable<number>
+  startedTime: number
+  finishedTime: Nullable<number>
+}
+
+export type SystemProcess = {
+  pid: number
+  ppid: number
+  name: string
+  cmdline: string[]
+  user: Nullable<string>
+  state: string
+  // percent of one core
+  cpu: number
+  memory: number
+  threads: number
+  startTime: number
+}
+
+export type SystemInfo = {
+  hostname: string
+  os: { name: Nullable<string>; kernel: Nullable<string>; arch: string }
+  cpuCount: number
+  cpuUsage: Nullable<number>
+  loadAverage: [number, number, number]
+  memory: { total: number; available: number; used: number; swapTotal: number; swapUsed: number }
+  uptime: number
+  bootTime: number
+}
+
+export type Event =
+  | { type: 'output'; seq: number; stream: 'stdout' | 'stderr'; line: string }
+  | { type: 'exit'; seq: number; code: Nullable<number>; signal: Nullable<number> }
+
+export type Action =
+  | 'open'
+  | 'reveal'
+  | 'output'
+  | 'presets'
+  | 'run-preset'
+  | 'spawn'
+  | 'status'
+  | 'list'
+  | 'write-stdin'
+  | 'kill'
+  | 'wait'
+  | 'remove'
+  | 'ps'
+  | 'kill-pid'
+  | 'system-info'
 
 export class Client {
   private request = getBridgeClientRequestor<Action>('process')
@@ -14,7 +121,83 @@ export class Client {
     return await this.request({ action: 'open', data: { path, using } })
   }
 
-  async output(cmd: string, args: string[] = [], options: Options = {}): Promise<Output> {
+  // shows the file selected in the file manager, or opens its folder
+  async reveal(path: string): Promise<void> {
+    return await this.request({ action: 'reveal', data: { path } })
+  }
+
+  async output(cmd: string, args: string[] = [], options: OutputOptions = {}): Promise<Output> {
     return await this.request({ action: 'output', data: { cmd, args, options } })
   }
+
+  async presets(): Promise<Dictionary<Preset>> {
+    return await this.request({ action: 'presets', data: {} })
+  }
+
+  async runPreset(
+    name: string,
+    params: Dictionary = {},
+    options: RunPresetOptions = {},
+  ): Promise<Output> {
+    return await this.request({ action: 'run-preset', data: { name, params, options } })
+  }
+
+  async spawn(cmd: string, args: string[] = [], options: Options = {}): Promise<Info> {
+    return await this.request({ action: 'spawn', data: { cmd, args, options } })
+  }
+
+  async status(id: number): Promise<Info> {
+    return await this.request({ action: 'status', data: { id } })
+  }
+
+  async list(): Promise<Info[]> {
+    return await this.request({ action: 'list', data: {} })
+  }
+
+  async writeStdin(id: number, data: string, close?: boolean): Promise<void> {
+    return await this.request({ action: 'write-stdin', data: { id, data, close } })
+  }
+
+  async kill(id: number, signal?: Signal): Promise<Info> {
+    return await this.request({ action: 'kill', data: { id, signal } })
+  }
+
+  // resolves with the process still running when the timeout runs out
+  async wait(id: number, timeoutMs?: number): Promise<Info> {
+    return await this.request({ action: 'wait', data: { id, timeoutMs } })
+  }
+
+  async remove(id: number): Promise<Info> {
+    return await this.request({ action: 'remove', data: { id } })
+  }
+
+  // cpu usage is measured over `intervalMs`, or averaged over each process's lifetime with 0
+  async ps(intervalMs?: number): Promise<SystemProcess[]> {
+    return await this.request({ action: 'ps', data: { intervalMs } })
+  }
+
+  async killPid(pid: number, signal?: Signal): Promise<void> {
+    return await this.request({ action: 'kill-pid', data: { pid, signal } })
+  }
+
+  async systemInfo(intervalMs?: number): Promise<SystemInfo> {
+    return await this.request({ action: 'system-info', data: { intervalMs } })
+  }
+
+  // `system` events every `intervalMs`, the first one without the cpu usage
+  system(intervalMs?: number): EventSource {
+    return new EventSource('/api/bridges/process/system' + stringifyQuery({ intervalMs }))
+  }
+
+  // `output` and `exit` events, replaying what was written after `since`
+  events(id: number, since?: number): EventSource {
+    return new EventSource('/api/bridges/process/events' + stringifyQuery({ id, since }))
+  }
+
+  // same events as json text messages, messages sent go to stdin
+  socket(id: number, since?: number): WebSocket {
+    const url = new URL('/api/bridges/process/ws' + stringifyQuery({ id, since }), location.href)
+    url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:'
+    return new WebSocket(url)
+  }
 }
@@ -0,0 +1,72 @@
+import { getBridgeClientRequestor } from '@/lib/utils'
+import type { Dictionary, Nullable } from '@/lib'
+import type { DownloadOptions } from '@/lib/fs'
+import type { Status } from '@/lib/jobs'
+
+// cron has five fields from minutes or six from seconds, `every` is in seconds
+export type Trigger = { cron: string } | { every: number }
+
+export type TaskAction =
+  | { type: 'preset'; name: string; params?: Dictionary }
+  | { type: 'download'; url: string; path: string; options?: DownloadOptions }
+  | {
+      type: 'http'
+      method?: string
+      url: string
+      headers?: Dictionary<string>
+      body?: unknown
+      timeoutMs?: number
+    }
+
+export type Missed = 'skip' | 'run-once'
+
+export type Task = { trigger: Trigger; action: TaskAction; missed?: Missed }
+
+export type TaskInfo = {
+  name: string
+  source: 'config' | 'added'
+  task: Task
+  nextTime: Nullable<number>
+  lastTime: Nullable<number>
+  running: boolean
+}
+
+export type Run = {
+  id: number
+  task: string
+  reason: 'schedule' | 'missed' | 'manual'
+  status: Status
+  jobId: Nullable<number>
+  result: unknown
+  error: Nullable<string>
+  startedTime: number
+  finishedTime: Nullable<number>
+}
+
+export type Action = 'add' | 'remove' | 'list' | 'run-now' | 'history'
+
+export class Client {
+  private request = getBridgeClientRequestor<Action>('schedule')
+
+  async add(name: string, task: Task): Promise<TaskInfo> {
+    return await this.request({ action: 'add', data: { name, task } })
+  }
+
+  // only tasks added here can be removed, not the ones in the config
+  async remove(name: string): Promise<TaskInfo> {
+    return await this.request({ action: 'remove', data: { name } })
+  }
+
+  async list(): Promise<TaskInfo[]> {
+    return await this.request({ action: 'list', data: {} })
+  }
+
+  async runNow(name: string): Promise<Run> {
+    return await this.request({ action: 'run-now', data: { name } })
+  }
+
+  // newest first
+  async history(name?: string, limit?: number): Promise<Run[]> {
+    return await this.request({ action: 'history', data: { name, limit } })
+  }
+}
@@ -0,0 +1,82 @@
+import { getBridgeClientRequestor } from '@/lib/utils'
+import { stringifyQuery } from '@/lib/urls'
+import type { Nullable } from '@/lib'
+import type { Env, Signal, Status } from '@/lib/process'
+
+export type SpawnOptions = {
+  cmd?: string
+  args?: string[]
+  cwd?: string
+  env?: Env
+  rows?: number
+  cols?: number
+}
+
+export type Info = {
+  id: number
+  pid: Nullable<number>
+  cmd: string
+  args: string[]
+  rows: number
+  cols: number
+  status: Status
+  code: Nullable<number>
+  signal: Nullable<number>
+  startedTime: number
+  finishedTime: Nullable<number>
+}
+
+export type Control =
+  | { type: 'input'; data: string }
+  | { type: 'resize'; rows: number; cols: number }
+  | { type: 'exit'; code: Nullable<number>; signal: Nullable<number> }
+
+export type Action = 'spawn' | 'status' | 'list' | 'input' | 'resize' | 'kill' | 'wait' | 'remove'
+
+export class Client {
+  private request = getBridgeClientRequestor<Action>('terminal')
+
+  // runs the user's shell when `cmd` is not set
+  async spawn(options: SpawnOptions = {}): Promise<Info> {
+    return await this.request({ action: 'spawn', data: { options } })
+  }
+
+  async status(id: number): Promise<Info> {
+    return await this.request({ action: 'status', data: { id } })
+  }
+
+  async list(): Promise<Info[]> {
+    return await this.request({ action: 'list', data: {} })
+  }
+
+  async input(id: number, data: string): Promise<void> {
+    return await this.request({ action: 'input', data: { id, data } })
+  }
+
+  async resize(id: number, rows: number, cols: number): Promise<Info> {
+    return await this.request({ action: 'resize', data: { id, rows, cols } })
+  }
+
+  // hangs up by default
+  async kill(id: number, signal?: Signal): Promise<Info> {
+    return await this.request({ action: 'kill', data: { id, signal } })
+  }
+
+  async wait(id: number, timeoutMs?: number): Promise<Info> {
+    return await this.request({ action: 'wait', data: { id, timeoutMs } })
+  }
+
+  async remove(id: number): Promise<Info> {
+    return await this.request({ action: 'remove', data: { id } })
+  }
+
+  // terminal i/o as binary messages, `Control` messages as json text
+  socket(id: number): WebSocket {
+    const url = new URL('/api/bridges/terminal/ws' + stringifyQuery({ id }), location.href)
+    url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:'
+
+    const socket = new WebSocket(url)
+    socket.binaryType = 'arraybuffer'
+    return socket
+  }
+}
@@ -1,3 +1,4 @@
+import type { Nullable } from '@/lib'
 import qs from 'qs'
 import type { IStringifyOptions as ExternalStringifyOptions } from 'qs'
 
@@ -7,6 +8,8 @@ const DEFAULT_STRINGIFY_OPTIONS: StringifyOptions = {
   arrayFormat: 'repeat',
 }
 
+export const CONTAINER_SEP = '>'
+
 export type StringifyOptions = ExternalStringifyOptions & { append?: boolean }
 
 export function stringifyQuery<Q>(query: Q, options: StringifyOptions = {}) {
@@ -19,6 +22,39 @@ export function getProxyUrl(url: string) {
   return `/api/bridges/proxy/${url}`
 }
 
-export function getAssetUrl(path: string, container?: string) {
-  return `/api/bridges/asset/${path}` + stringifyQuery({ container })
+export type ThumbnailOptions = {
+  w?: number
+  h?: number
+  fit?: 'contain' | 'cover' | 'fill'
+  format?: 'jpeg' | 'png' | 'webp'
+}
+
+// segments after the first are entries, nested archives included:
+// ['bundle.zip', 'vol1.cbz', '001.jpg']
+export function getAssetUrl(
+  path: string | string[],
+  container?: string,
+  thumbnail?: ThumbnailOptions,
+) {
+  if (Array.isArray(path)) path = path.join(CONTAINER_SEP)
+  return `/api/bridges/asset/${path}` + stringifyQuery({ container, ...thumbnail })
+}
+
+// what the asset url returns for directories and archive roots like `book.cbz>`
+export type AssetListing = {
+  path: string
+  parent: Nullable<string>
+  entries: AssetListingEntry[]
+}
+
+export type AssetListingEntry = {
+  name: string
+  path: string
+  isDir: boolean
+  isArchive: boolean
+  size: Nullable<number>
+  modifiedTime: Nullable<number>
+  mime: Nullable<string>
+  // stats couldn't be read, the fields above are null
+  unreadable: boolean
 }